@@ -14,13 +14,23 @@
 // You should have received a copy of the GNU General Public License
 // along with this program. If not, see <https://www.gnu.org/licenses/>.
 
-use acpi::{AcpiHandler, AcpiTables, PhysicalMapping};
+use crate::logger::{Level, LOGGER};
+use crate::memory;
+use crate::{debug, warn};
+use acpi::fadt::Fadt;
+use acpi::{AcpiHandler, AcpiTables, HpetInfo, PhysicalMapping};
+use alloc::format;
+use core::ptr::NonNull;
 use limine::request::RsdpRequest;
+use spin::Lazy;
+use x86_64::PhysAddr;
 
 #[used]
 #[link_section = ".requests"]
 static RSDP_REQUEST: RsdpRequest = RsdpRequest::new();
 
+pub static ACPI: Lazy<Acpi> = Lazy::new(Acpi::new);
+
 #[derive(Clone)]
 struct Handler;
 
@@ -30,25 +40,58 @@ impl AcpiHandler for Handler {
         physical_address: usize,
         size: usize,
     ) -> PhysicalMapping<Self, T> {
-        todo!()
+        let virtual_address = memory::physical_to_virtual(PhysAddr::new(physical_address as u64));
+        PhysicalMapping::new(
+            physical_address,
+            NonNull::new(virtual_address.as_mut_ptr()).unwrap(),
+            size,
+            size,
+            self.clone(),
+        )
     }
 
-    fn unmap_physical_region<T>(region: &PhysicalMapping<Self, T>) {
-        todo!()
-    }
+    fn unmap_physical_region<T>(_region: &PhysicalMapping<Self, T>) {}
 }
 
-struct Acpi {
-    tables: AcpiTables<Handler>,
+pub struct Acpi {
+    century: u8,
+    hpet: Option<HpetInfo>,
 }
 
 impl Acpi {
     pub fn new() -> Acpi {
-        let rsdp = RSDP_REQUEST.get_response().unwrap().address() as usize;
-        let handler = Handler;
-        unsafe {
-            let tables = AcpiTables::from_rsdp(handler, rsdp).unwrap();
-            Acpi { tables }
-        }
+        let address = RSDP_REQUEST.get_response().unwrap().address() as u64;
+        let rsdp = memory::virtual_to_physical(address);
+        let tables = unsafe {
+            AcpiTables::from_rsdp(Handler, usize::try_from(rsdp.as_u64()).unwrap()).unwrap()
+        };
+        debug!("Found ACPI tables (revision {}).", tables.revision());
+        let hpet = match HpetInfo::new(&tables) {
+            Ok(hpet) => Some(hpet),
+            Err(error) => {
+                warn!("Failed to find the HPET table: {error:?}");
+                None
+            }
+        };
+        let century = match tables.find_table::<Fadt>() {
+            Ok(fadt) => fadt.century,
+            Err(error) => {
+                warn!("Failed to find the FADT table: {error:?}");
+                0
+            }
+        };
+        Acpi { century, hpet }
+    }
+
+    pub fn get_century(&self) -> u8 {
+        self.century
     }
+
+    pub fn get_hpet(&self) -> Option<&HpetInfo> {
+        self.hpet.as_ref()
+    }
+}
+
+pub fn initialize() {
+    Lazy::force(&ACPI);
 }
@@ -16,8 +16,10 @@
 
 pub const BLUE: &str = "\x1b[38;2;0;151;230m";
 pub const BOLD: &str = "\x1b[1m";
+pub const CLEAR: &str = "\x1b[2J";
 pub const DEFAULT: &str = "\x1b[39m";
 pub const GREEN: &str = "\x1b[38;2;68;189;50m";
+pub const HOME: &str = "\x1b[H";
 pub const NORMAL: &str = "\x1b[0m";
 pub const ORANGE: &str = "\x1b[38;2;194;54;52m";
 pub const PURPLE: &str = "\x1b[38;2;140;122;230m";
@@ -0,0 +1,197 @@
+// NeurOS - Hobbyist operating system written in Rust.
+// Copyright (C) 2024 Theomund
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program. If not, see <https://www.gnu.org/licenses/>.
+
+use crate::interrupts::InterruptIndex;
+use crate::logger::{Level, LOGGER};
+use crate::timer::{self, ClockEvent};
+use crate::{debug, memory};
+use alloc::format;
+use core::sync::atomic::{AtomicU64, Ordering};
+use core::{hint, ptr};
+use spin::Once;
+use x86_64::instructions::interrupts;
+use x86_64::registers::model_specific::Msr;
+use x86_64::{PhysAddr, VirtAddr};
+
+const APIC_BASE_MSR: u32 = 0x1B;
+const APIC_ENABLE: u64 = 1 << 11;
+const CALIBRATION_NANOSECONDS: u64 = 10_000_000;
+const NANOSECONDS_PER_SECOND: u64 = 1_000_000_000;
+
+const SOFTWARE_ENABLE: u32 = 1 << 8;
+const TIMER_MASKED: u32 = 1 << 16;
+const TIMER_PERIODIC: u32 = 1 << 17;
+const DIVIDE_BY_16: u32 = 0b0011;
+
+const DELIVERY_NMI: u32 = 0b100 << 8;
+const DELIVERY_PENDING: u32 = 1 << 12;
+const LEVEL_ASSERT: u32 = 1 << 14;
+const ALL_EXCLUDING_SELF: u32 = 0b11 << 18;
+
+pub static LAPIC: Once<Lapic> = Once::new();
+
+#[repr(u64)]
+enum Register {
+    Id = 0x020,
+    EndOfInterrupt = 0x0B0,
+    SpuriousVector = 0x0F0,
+    InterruptCommandLow = 0x300,
+    InterruptCommandHigh = 0x310,
+    TimerVector = 0x320,
+    TimerInitialCount = 0x380,
+    TimerCurrentCount = 0x390,
+    TimerDivide = 0x3E0,
+}
+
+pub struct Lapic {
+    address: VirtAddr,
+    frequency: AtomicU64,
+}
+
+impl Lapic {
+    pub fn new() -> Lapic {
+        let base = unsafe { Msr::new(APIC_BASE_MSR).read() };
+        let address = memory::map_mmio(PhysAddr::new(base & 0xF_FFFF_F000), 0x1000);
+        Lapic {
+            address,
+            frequency: AtomicU64::new(0),
+        }
+    }
+
+    fn read(&self, register: Register) -> u32 {
+        let pointer = (self.address + register as u64).as_ptr::<u32>();
+        unsafe { ptr::read_volatile(pointer) }
+    }
+
+    fn write(&self, register: Register, value: u32) {
+        let pointer = (self.address + register as u64).as_mut_ptr::<u32>();
+        unsafe {
+            ptr::write_volatile(pointer, value);
+        }
+    }
+
+    pub fn enable(&self) {
+        unsafe {
+            let mut msr = Msr::new(APIC_BASE_MSR);
+            let base = msr.read();
+            msr.write(base | APIC_ENABLE);
+        }
+        self.write(
+            Register::SpuriousVector,
+            SOFTWARE_ENABLE | u32::from(InterruptIndex::Spurious as u8),
+        );
+        self.write(Register::TimerDivide, DIVIDE_BY_16);
+        self.write(Register::TimerVector, TIMER_MASKED);
+    }
+
+    pub fn get_id(&self) -> u32 {
+        self.read(Register::Id) >> 24
+    }
+
+    pub fn end_of_interrupt(&self) {
+        self.write(Register::EndOfInterrupt, 0);
+    }
+
+    fn send(&self, destination: u32, command: u32) {
+        interrupts::without_interrupts(|| {
+            while self.read(Register::InterruptCommandLow) & DELIVERY_PENDING != 0 {
+                hint::spin_loop();
+            }
+            self.write(Register::InterruptCommandHigh, destination << 24);
+            self.write(Register::InterruptCommandLow, command);
+        });
+    }
+
+    pub fn send_ipi(&self, apic_id: u32, vector: u8) {
+        self.send(apic_id, LEVEL_ASSERT | u32::from(vector));
+    }
+
+    pub fn send_nmi_to_others(&self) {
+        self.send(0, ALL_EXCLUDING_SELF | LEVEL_ASSERT | DELIVERY_NMI);
+    }
+
+    fn calibrate(&self) -> u64 {
+        self.write(Register::TimerVector, TIMER_MASKED);
+        self.write(Register::TimerInitialCount, u32::MAX);
+        timer::delay(CALIBRATION_NANOSECONDS);
+        let elapsed = u32::MAX - self.read(Register::TimerCurrentCount);
+        self.write(Register::TimerInitialCount, 0);
+        let frequency = u64::from(elapsed) * NANOSECONDS_PER_SECOND / CALIBRATION_NANOSECONDS;
+        self.frequency.store(frequency, Ordering::Relaxed);
+        frequency
+    }
+
+    fn nanoseconds_to_count(&self, nanoseconds: u64) -> u32 {
+        let frequency = u128::from(self.frequency.load(Ordering::Relaxed));
+        let count = u128::from(nanoseconds) * frequency / u128::from(NANOSECONDS_PER_SECOND);
+        u32::try_from(count).unwrap_or(u32::MAX).max(1)
+    }
+}
+
+impl ClockEvent for Lapic {
+    fn get_name(&self) -> &'static str {
+        "LAPIC"
+    }
+
+    fn get_rating(&self) -> u32 {
+        if self.frequency.load(Ordering::Relaxed) == 0 {
+            0
+        } else {
+            200
+        }
+    }
+
+    fn start_periodic(&self, frequency: u32) -> u64 {
+        let count = self.nanoseconds_to_count(NANOSECONDS_PER_SECOND / u64::from(frequency));
+        self.write(
+            Register::TimerVector,
+            TIMER_PERIODIC | u32::from(InterruptIndex::ApicTimer as u8),
+        );
+        self.write(Register::TimerInitialCount, count);
+        u64::from(count) * NANOSECONDS_PER_SECOND / self.frequency.load(Ordering::Relaxed)
+    }
+
+    fn start_oneshot(&self, nanoseconds: u64) {
+        let count = self.nanoseconds_to_count(nanoseconds);
+        self.write(
+            Register::TimerVector,
+            u32::from(InterruptIndex::ApicTimer as u8),
+        );
+        self.write(Register::TimerInitialCount, count);
+    }
+
+    fn stop(&self) {
+        self.write(Register::TimerVector, TIMER_MASKED);
+        self.write(Register::TimerInitialCount, 0);
+    }
+}
+
+pub fn end_of_interrupt() {
+    if let Some(lapic) = LAPIC.get() {
+        lapic.end_of_interrupt();
+    }
+}
+
+pub fn initialize() -> &'static Lapic {
+    let lapic = LAPIC.call_once(Lapic::new);
+    lapic.enable();
+    let frequency = lapic.calibrate();
+    debug!(
+        "Calibrated the local APIC timer of CPU #{} to {frequency} Hz.",
+        lapic.get_id()
+    );
+    lapic
+}
@@ -0,0 +1,326 @@
+// NeurOS - Hobbyist operating system written in Rust.
+// Copyright (C) 2024 Theomund
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program. If not, see <https://www.gnu.org/licenses/>.
+
+use crate::interrupts;
+use crate::sync::IrqMutex;
+use crate::wait::Queue;
+use core::cell::UnsafeCell;
+use core::ops::{Deref, DerefMut};
+use core::sync::atomic::{AtomicBool, AtomicU32, AtomicU64, Ordering};
+
+fn assert_sleepable(name: &str) {
+    debug_assert!(
+        !interrupts::in_interrupt(),
+        "Attempted to sleep on the {name} lock inside an interrupt handler."
+    );
+}
+
+pub struct Mutex<T> {
+    name: &'static str,
+    locked: AtomicBool,
+    queue: Queue,
+    value: UnsafeCell<T>,
+}
+
+unsafe impl<T: Send> Send for Mutex<T> {}
+unsafe impl<T: Send> Sync for Mutex<T> {}
+
+pub struct MutexGuard<'a, T> {
+    mutex: &'a Mutex<T>,
+}
+
+impl<T> Mutex<T> {
+    pub const fn new(name: &'static str, value: T) -> Mutex<T> {
+        Mutex {
+            name,
+            locked: AtomicBool::new(false),
+            queue: Queue::new(name),
+            value: UnsafeCell::new(value),
+        }
+    }
+
+    pub fn lock(&self) -> MutexGuard<'_, T> {
+        assert_sleepable(self.name);
+        if !self.acquire() {
+            self.queue.wait_until(|| self.acquire());
+        }
+        MutexGuard { mutex: self }
+    }
+
+    fn acquire(&self) -> bool {
+        self.locked
+            .compare_exchange(false, true, Ordering::Acquire, Ordering::Relaxed)
+            .is_ok()
+    }
+}
+
+impl<T> Deref for MutexGuard<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        unsafe { &*self.mutex.value.get() }
+    }
+}
+
+impl<T> DerefMut for MutexGuard<'_, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        unsafe { &mut *self.mutex.value.get() }
+    }
+}
+
+impl<T> Drop for MutexGuard<'_, T> {
+    fn drop(&mut self) {
+        self.mutex.locked.store(false, Ordering::Release);
+        self.mutex.queue.wake_one();
+    }
+}
+
+pub struct Semaphore {
+    name: &'static str,
+    count: AtomicU32,
+    queue: Queue,
+}
+
+impl Semaphore {
+    pub const fn new(name: &'static str, count: u32) -> Semaphore {
+        Semaphore {
+            name,
+            count: AtomicU32::new(count),
+            queue: Queue::new(name),
+        }
+    }
+
+    pub fn down(&self) {
+        assert_sleepable(self.name);
+        self.queue.wait_until(|| self.try_down());
+    }
+
+    pub fn try_down(&self) -> bool {
+        self.count
+            .fetch_update(Ordering::Acquire, Ordering::Relaxed, |x| x.checked_sub(1))
+            .is_ok()
+    }
+
+    pub fn up(&self) {
+        self.count.fetch_add(1, Ordering::Release);
+        self.queue.wake_one();
+    }
+}
+
+pub struct Condvar {
+    sequence: AtomicU64,
+    queue: Queue,
+}
+
+impl Condvar {
+    pub const fn new(name: &'static str) -> Condvar {
+        Condvar {
+            sequence: AtomicU64::new(0),
+            queue: Queue::new(name),
+        }
+    }
+
+    pub fn wait<'a, T>(&self, guard: MutexGuard<'a, T>) -> MutexGuard<'a, T> {
+        let mutex = guard.mutex;
+        assert_sleepable(mutex.name);
+        let sequence = self.sequence.load(Ordering::Acquire);
+        drop(guard);
+        self.queue
+            .wait_until(|| self.sequence.load(Ordering::Acquire) != sequence);
+        mutex.lock()
+    }
+
+    pub fn wait_while<'a, T, F: FnMut(&mut T) -> bool>(
+        &self,
+        mut guard: MutexGuard<'a, T>,
+        mut condition: F,
+    ) -> MutexGuard<'a, T> {
+        while condition(&mut guard) {
+            guard = self.wait(guard);
+        }
+        guard
+    }
+
+    pub fn notify_all(&self) {
+        self.sequence.fetch_add(1, Ordering::Release);
+        self.queue.wake_all();
+    }
+}
+
+struct Counts {
+    readers: u32,
+    writer: bool,
+    waiting: u32,
+}
+
+pub struct RwLock<T> {
+    name: &'static str,
+    counts: IrqMutex<Counts>,
+    readers: Queue,
+    writers: Queue,
+    value: UnsafeCell<T>,
+}
+
+unsafe impl<T: Send> Send for RwLock<T> {}
+unsafe impl<T: Send + Sync> Sync for RwLock<T> {}
+
+pub struct RwLockReadGuard<'a, T> {
+    lock: &'a RwLock<T>,
+}
+
+pub struct RwLockWriteGuard<'a, T> {
+    lock: &'a RwLock<T>,
+}
+
+impl<T> RwLock<T> {
+    pub const fn new(name: &'static str, value: T) -> RwLock<T> {
+        RwLock {
+            name,
+            counts: IrqMutex::new(
+                name,
+                Counts {
+                    readers: 0,
+                    writer: false,
+                    waiting: 0,
+                },
+            ),
+            readers: Queue::new(name),
+            writers: Queue::new(name),
+            value: UnsafeCell::new(value),
+        }
+    }
+
+    pub fn read(&self) -> RwLockReadGuard<'_, T> {
+        assert_sleepable(self.name);
+        self.readers.wait_until(|| {
+            let mut counts = self.counts.lock();
+            let available = !counts.writer && counts.waiting == 0;
+            if available {
+                counts.readers += 1;
+            }
+            available
+        });
+        RwLockReadGuard { lock: self }
+    }
+
+    pub fn try_read(&self) -> Option<RwLockReadGuard<'_, T>> {
+        let mut counts = self.counts.lock();
+        if counts.writer || counts.waiting != 0 {
+            return None;
+        }
+        counts.readers += 1;
+        Some(RwLockReadGuard { lock: self })
+    }
+
+    pub fn write(&self) -> RwLockWriteGuard<'_, T> {
+        assert_sleepable(self.name);
+        self.counts.lock().waiting += 1;
+        self.writers.wait_until(|| {
+            let mut counts = self.counts.lock();
+            let available = !counts.writer && counts.readers == 0;
+            if available {
+                counts.writer = true;
+                counts.waiting -= 1;
+            }
+            available
+        });
+        RwLockWriteGuard { lock: self }
+    }
+}
+
+impl<T> Deref for RwLockReadGuard<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        unsafe { &*self.lock.value.get() }
+    }
+}
+
+impl<T> Drop for RwLockReadGuard<'_, T> {
+    fn drop(&mut self) {
+        let mut counts = self.lock.counts.lock();
+        counts.readers -= 1;
+        let last = counts.readers == 0;
+        drop(counts);
+        if last {
+            self.lock.writers.wake_one();
+        }
+    }
+}
+
+impl<T> Deref for RwLockWriteGuard<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        unsafe { &*self.lock.value.get() }
+    }
+}
+
+impl<T> DerefMut for RwLockWriteGuard<'_, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        unsafe { &mut *self.lock.value.get() }
+    }
+}
+
+impl<T> Drop for RwLockWriteGuard<'_, T> {
+    fn drop(&mut self) {
+        let mut counts = self.lock.counts.lock();
+        counts.writer = false;
+        let waiting = counts.waiting != 0;
+        drop(counts);
+        if waiting {
+            self.lock.writers.wake_one();
+        } else {
+            self.lock.readers.wake_all();
+        }
+    }
+}
+
+pub struct Completion {
+    name: &'static str,
+    done: AtomicU32,
+    queue: Queue,
+}
+
+impl Completion {
+    pub const fn new(name: &'static str) -> Completion {
+        Completion {
+            name,
+            done: AtomicU32::new(0),
+            queue: Queue::new(name),
+        }
+    }
+
+    pub fn wait(&self) {
+        assert_sleepable(self.name);
+        self.queue.wait_until(|| self.consume());
+    }
+
+    pub fn complete_all(&self) {
+        self.done.store(u32::MAX, Ordering::Release);
+        self.queue.wake_all();
+    }
+
+    fn consume(&self) -> bool {
+        self.done
+            .fetch_update(Ordering::Acquire, Ordering::Relaxed, |x| match x {
+                0 => None,
+                u32::MAX => Some(x),
+                _ => Some(x - 1),
+            })
+            .is_ok()
+    }
+}
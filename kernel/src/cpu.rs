@@ -0,0 +1,281 @@
+// NeurOS - Hobbyist operating system written in Rust.
+// Copyright (C) 2024 Theomund
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program. If not, see <https://www.gnu.org/licenses/>.
+
+use crate::gdt::{self, IST_COUNT, IST_SIZE};
+use crate::ipi::Call;
+use crate::scheduler::Scheduler;
+use crate::sync::IrqMutex;
+use alloc::boxed::Box;
+use alloc::collections::VecDeque;
+use alloc::vec;
+use alloc::vec::Vec;
+use core::arch::asm;
+use core::mem;
+use core::ptr;
+use core::sync::atomic::{AtomicBool, AtomicPtr, AtomicU32, AtomicU64, Ordering};
+use x86_64::registers::control::Cr3;
+use x86_64::registers::model_specific::{GsBase, KernelGsBase};
+use x86_64::structures::paging::PhysFrame;
+use x86_64::structures::tss::TaskStateSegment;
+use x86_64::VirtAddr;
+
+pub const UNKNOWN: u32 = u32::MAX;
+
+static CPUS: IrqMutex<Vec<&'static Cpu>> = IrqMutex::new("CPUS", Vec::new());
+
+static EARLY: Early = Early {
+    this: 0,
+    kernel_stack: AtomicU64::new(0),
+    user_stack: AtomicU64::new(0),
+    id: UNKNOWN,
+    nesting: AtomicU32::new(0),
+};
+
+#[repr(C)]
+struct Early {
+    this: u64,
+    kernel_stack: AtomicU64,
+    user_stack: AtomicU64,
+    id: u32,
+    nesting: AtomicU32,
+}
+
+const _: () = assert!(mem::offset_of!(Early, id) == mem::offset_of!(Cpu, id));
+const _: () = assert!(mem::offset_of!(Early, nesting) == mem::offset_of!(Cpu, nesting));
+
+#[derive(Default)]
+pub struct Statistics {
+    interrupts: AtomicU64,
+    switches: AtomicU64,
+    ticks: AtomicU64,
+}
+
+impl Statistics {
+    pub fn get_interrupts(&self) -> u64 {
+        self.interrupts.load(Ordering::Relaxed)
+    }
+
+    pub fn get_switches(&self) -> u64 {
+        self.switches.load(Ordering::Relaxed)
+    }
+
+    pub fn get_ticks(&self) -> u64 {
+        self.ticks.load(Ordering::Relaxed)
+    }
+
+    pub fn count_interrupt(&self) {
+        self.interrupts.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn count_switch(&self) {
+        self.switches.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn count_tick(&self) {
+        self.ticks.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+#[repr(C)]
+pub struct Cpu {
+    this: u64,
+    kernel_stack: AtomicU64,
+    user_stack: AtomicU64,
+    id: u32,
+    nesting: AtomicU32,
+    apic_id: u32,
+    bootstrap: bool,
+    current: AtomicU64,
+    stopped: AtomicBool,
+    address_space: AtomicU64,
+    calls: IrqMutex<VecDeque<Call>>,
+    stacks: [VirtAddr; IST_COUNT],
+    tss: AtomicPtr<TaskStateSegment>,
+    scheduler: IrqMutex<Scheduler>,
+    statistics: Statistics,
+}
+
+impl Cpu {
+    fn new(id: u32, apic_id: u32, bootstrap: bool) -> Cpu {
+        let stacks = core::array::from_fn(|_| {
+            let stack = vec![0u8; IST_SIZE].leak();
+            VirtAddr::from_ptr(stack.as_ptr()) + IST_SIZE as u64
+        });
+        Cpu {
+            this: 0,
+            kernel_stack: AtomicU64::new(0),
+            user_stack: AtomicU64::new(0),
+            id,
+            nesting: AtomicU32::new(0),
+            apic_id,
+            bootstrap,
+            current: AtomicU64::new(0),
+            stopped: AtomicBool::new(false),
+            address_space: AtomicU64::new(Cr3::read().0.start_address().as_u64()),
+            calls: IrqMutex::new("CALLS", VecDeque::new()),
+            stacks,
+            tss: AtomicPtr::new(ptr::null_mut()),
+            scheduler: IrqMutex::new("SCHEDULER", Scheduler::new(10)),
+            statistics: Statistics::default(),
+        }
+    }
+
+    pub fn get_id(&self) -> u32 {
+        self.id
+    }
+
+    pub fn get_apic_id(&self) -> u32 {
+        self.apic_id
+    }
+
+    pub fn is_bootstrap(&self) -> bool {
+        self.bootstrap
+    }
+
+    pub fn get_address_space(&self) -> u64 {
+        self.address_space.load(Ordering::Relaxed)
+    }
+
+    pub fn get_calls(&self) -> &IrqMutex<VecDeque<Call>> {
+        &self.calls
+    }
+
+    pub fn get_current(&self) -> u64 {
+        self.current.load(Ordering::Relaxed)
+    }
+
+    pub fn get_scheduler(&self) -> &IrqMutex<Scheduler> {
+        &self.scheduler
+    }
+
+    pub fn get_statistics(&self) -> &Statistics {
+        &self.statistics
+    }
+
+    pub fn set_address_space(&self, frame: PhysFrame) {
+        self.address_space
+            .store(frame.start_address().as_u64(), Ordering::Relaxed);
+    }
+
+    pub fn set_tick_stopped(&self, stopped: bool) -> bool {
+        self.stopped.swap(stopped, Ordering::Relaxed)
+    }
+
+    pub fn set_current(&self, pid: u64) {
+        self.current.store(pid, Ordering::Relaxed);
+    }
+
+    pub fn set_kernel_stack(&self, top: u64) {
+        self.kernel_stack.store(top, Ordering::Relaxed);
+        let tss = self.tss.load(Ordering::Relaxed);
+        if !tss.is_null() {
+            unsafe {
+                let table = ptr::addr_of_mut!((*tss).privilege_stack_table);
+                let mut stacks = table.read_unaligned();
+                stacks[0] = VirtAddr::new(top);
+                table.write_unaligned(stacks);
+            }
+        }
+    }
+}
+
+pub fn current() -> &'static Cpu {
+    let pointer: u64;
+    unsafe {
+        asm!(
+            "mov {}, gs:[0]",
+            out(reg) pointer,
+            options(nostack, readonly, preserves_flags)
+        );
+        &*(pointer as *const Cpu)
+    }
+}
+
+#[cfg(any(debug_assertions, feature = "lockdep"))]
+pub fn get_current_id() -> u32 {
+    let id: u32;
+    unsafe {
+        asm!(
+            "mov {:e}, gs:[{offset}]",
+            out(reg) id,
+            offset = const mem::offset_of!(Cpu, id),
+            options(nostack, readonly, preserves_flags)
+        );
+    }
+    id
+}
+
+pub fn get_nesting() -> u32 {
+    let nesting: u32;
+    unsafe {
+        asm!(
+            "mov {:e}, gs:[{offset}]",
+            out(reg) nesting,
+            offset = const mem::offset_of!(Cpu, nesting),
+            options(nostack, readonly, preserves_flags)
+        );
+    }
+    nesting
+}
+
+pub fn enter_interrupt() {
+    unsafe {
+        asm!(
+            "inc dword ptr gs:[{offset}]",
+            offset = const mem::offset_of!(Cpu, nesting),
+            options(nostack)
+        );
+    }
+}
+
+pub fn leave_interrupt() {
+    unsafe {
+        asm!(
+            "dec dword ptr gs:[{offset}]",
+            offset = const mem::offset_of!(Cpu, nesting),
+            options(nostack)
+        );
+    }
+}
+
+pub fn get(id: u32) -> Option<&'static Cpu> {
+    CPUS.lock().iter().copied().find(|x| x.id == id)
+}
+
+pub fn get_cpus() -> Vec<&'static Cpu> {
+    CPUS.lock().clone()
+}
+
+pub fn create(id: u32, apic_id: u32, bootstrap: bool) -> &'static Cpu {
+    let cpu = Box::leak(Box::new(Cpu::new(id, apic_id, bootstrap)));
+    cpu.this = ptr::from_ref::<Cpu>(cpu) as u64;
+    cpu
+}
+
+pub fn initialize_early() {
+    GsBase::write(VirtAddr::from_ptr(&EARLY));
+}
+
+pub fn initialize(cpu: &'static Cpu) {
+    GsBase::write(VirtAddr::new(cpu.this));
+    KernelGsBase::write(VirtAddr::zero());
+    let tss = gdt::initialize_cpu(&cpu.stacks);
+    cpu.tss.store(tss, Ordering::Relaxed);
+}
+
+pub fn online(cpu: &'static Cpu) {
+    CPUS.lock().push(cpu);
+}
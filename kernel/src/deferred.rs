@@ -0,0 +1,128 @@
+// NeurOS - Hobbyist operating system written in Rust.
+// Copyright (C) 2024 Theomund
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program. If not, see <https://www.gnu.org/licenses/>.
+
+use crate::blocking::Semaphore;
+use crate::logger::{Level, LOGGER};
+use crate::sync::IrqMutex;
+use crate::{thread, warn};
+use alloc::format;
+use core::sync::atomic::{AtomicBool, Ordering};
+use spin::Lazy;
+
+const CAPACITY: usize = 256;
+
+static PENDING: Semaphore = Semaphore::new("DEFERRED_PENDING", 0);
+static SIGNALED: AtomicBool = AtomicBool::new(false);
+
+static QUEUE: Lazy<IrqMutex<Queue>> = Lazy::new(|| {
+    let queue = Queue::new();
+    IrqMutex::new("DEFERRED", queue)
+});
+
+#[derive(Clone, Copy)]
+pub struct Work {
+    handler: fn(u8),
+    data: u8,
+}
+
+impl Work {
+    pub fn new(handler: fn(u8), data: u8) -> Work {
+        Work { handler, data }
+    }
+
+    fn run(self) {
+        (self.handler)(self.data);
+    }
+}
+
+struct Queue {
+    items: [Option<Work>; CAPACITY],
+    head: usize,
+    length: usize,
+    dropped: u64,
+}
+
+impl Queue {
+    fn new() -> Queue {
+        Queue {
+            items: [None; CAPACITY],
+            head: 0,
+            length: 0,
+            dropped: 0,
+        }
+    }
+
+    fn push(&mut self, work: Work) {
+        if self.length == CAPACITY {
+            self.dropped += 1;
+            return;
+        }
+        let tail = (self.head + self.length) % CAPACITY;
+        self.items[tail] = Some(work);
+        self.length += 1;
+    }
+
+    fn pop(&mut self) -> Option<Work> {
+        if self.length == 0 {
+            return None;
+        }
+        let work = self.items[self.head].take();
+        self.head = (self.head + 1) % CAPACITY;
+        self.length -= 1;
+        work
+    }
+
+    fn take_dropped(&mut self) -> u64 {
+        let dropped = self.dropped;
+        self.dropped = 0;
+        dropped
+    }
+}
+
+pub fn schedule(work: Work) {
+    QUEUE.lock().push(work);
+    SIGNALED.store(true, Ordering::Release);
+}
+
+/// Wakes the worker for work scheduled from interrupt handlers. This runs
+/// from the idle loop and the tick so the handlers never touch the process
+/// table or the run queues.
+pub fn notify() {
+    if SIGNALED.swap(false, Ordering::AcqRel) {
+        PENDING.up();
+    }
+}
+
+pub fn run() {
+    let dropped = QUEUE.lock().take_dropped();
+    if dropped != 0 {
+        warn!("Dropped {dropped} deferred work item(s) because the queue was full.");
+    }
+    while let Some(work) = QUEUE.lock().pop() {
+        work.run();
+    }
+}
+
+fn worker() {
+    loop {
+        PENDING.down();
+        run();
+    }
+}
+
+pub fn initialize() {
+    thread::spawn("deferred", worker);
+}
@@ -18,6 +18,19 @@ use crate::initrd::INITRD;
 use alloc::vec::Vec;
 use core::fmt::{Display, Formatter};
 
+const MAGIC: u32 = 0x464C_457F;
+const CLASS_64: u8 = 2;
+const LITTLE_ENDIAN: u8 = 1;
+const EXECUTABLE: u16 = 2;
+const AMD64: u16 = 0x3E;
+const HEADER_SIZE: usize = 64;
+const PROGRAM_SIZE: usize = 56;
+const SECTION_SIZE: usize = 64;
+const LOAD: u32 = 1;
+const EXECUTE: u32 = 1;
+const WRITE: u32 = 2;
+const READ: u32 = 4;
+
 struct Header {
     magic: u32,
     class: u8,
@@ -72,7 +85,7 @@ impl Header {
     }
 }
 
-struct Program {
+pub struct Program {
     segment_type: u32,
     flags: u32,
     offset: u64,
@@ -152,6 +165,74 @@ impl Section {
     }
 }
 
+impl Program {
+    pub fn get_offset(&self) -> u64 {
+        self.offset
+    }
+
+    pub fn get_virtual_address(&self) -> u64 {
+        self.virtual_address
+    }
+
+    pub fn get_file_size(&self) -> u64 {
+        self.file_size
+    }
+
+    pub fn get_memory_size(&self) -> u64 {
+        self.memory_size
+    }
+
+    pub fn get_alignment(&self) -> u64 {
+        self.alignment
+    }
+
+    pub fn is_executable(&self) -> bool {
+        self.flags & EXECUTE != 0
+    }
+
+    pub fn is_writable(&self) -> bool {
+        self.flags & WRITE != 0
+    }
+}
+
+impl Display for Program {
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
+        let segment_type = match self.segment_type {
+            0 => "NULL",
+            LOAD => "LOAD",
+            2 => "DYNAMIC",
+            3 => "INTERP",
+            4 => "NOTE",
+            6 => "PHDR",
+            7 => "TLS",
+            0x6474_E550 => "GNU_EH_FRAME",
+            0x6474_E551 => "GNU_STACK",
+            0x6474_E552 => "GNU_RELRO",
+            _ => "Unknown",
+        };
+        let flag = |mask, character| {
+            if self.flags & mask == 0 {
+                ' '
+            } else {
+                character
+            }
+        };
+        write!(
+            f,
+            "{segment_type:<8} 0x{:08x} 0x{:016x} 0x{:016x} 0x{:08x} 0x{:08x} {}{}{} 0x{:x}",
+            self.offset,
+            self.virtual_address,
+            self.physical_address,
+            self.file_size,
+            self.memory_size,
+            flag(READ, 'R'),
+            flag(WRITE, 'W'),
+            flag(EXECUTE, 'E'),
+            self.alignment
+        )
+    }
+}
+
 pub struct Elf {
     header: Header,
     programs: Vec<Program>,
@@ -247,32 +328,64 @@ impl Display for Elf {
             "  Section header string table index: {}",
             self.header.sh_index
         )?;
+        writeln!(f, "Program Headers:")?;
+        writeln!(
+            f,
+            "  Type     Offset     VirtAddr           PhysAddr           FileSiz    MemSiz     Flg Align"
+        )?;
+        for program in &self.programs {
+            writeln!(f, "  {program}")?;
+        }
         Ok(())
     }
 }
 
 impl Elf {
     pub fn new(path: &str) -> Elf {
-        let data = INITRD.get_data(path);
-        let header = Header::new(data);
+        Elf::parse(INITRD.get_data(path)).expect("Failed to parse ELF file.")
+    }
+
+    pub fn parse(data: &[u8]) -> Option<Elf> {
+        let header = Header::new(data.get(..HEADER_SIZE)?);
         let mut programs: Vec<Program> = Vec::new();
         for index in 0..header.ph_number {
-            let offset = header.ph_offset + u64::from(index * header.ph_size);
-            let slice = &data[usize::try_from(offset).unwrap()..];
+            let offset = header.ph_offset + u64::from(index) * u64::from(header.ph_size);
+            let slice = Elf::get_table(data, offset, PROGRAM_SIZE)?;
             let program = Program::new(slice);
             programs.push(program);
         }
         let mut sections: Vec<Section> = Vec::new();
         for index in 0..header.sh_number {
-            let offset = header.sh_offset + u64::from(index * header.sh_size);
-            let slice = &data[usize::try_from(offset).unwrap()..];
+            let offset = header.sh_offset + u64::from(index) * u64::from(header.sh_size);
+            let slice = Elf::get_table(data, offset, SECTION_SIZE)?;
             let section = Section::new(slice);
             sections.push(section);
         }
-        Elf {
+        Some(Elf {
             header,
             programs,
             sections,
-        }
+        })
+    }
+
+    fn get_table(data: &[u8], offset: u64, size: usize) -> Option<&[u8]> {
+        let start = usize::try_from(offset).ok()?;
+        data.get(start..start.checked_add(size)?)
+    }
+
+    pub fn get_entrypoint(&self) -> u64 {
+        self.header.entrypoint
+    }
+
+    pub fn get_segments(&self) -> impl Iterator<Item = &Program> {
+        self.programs.iter().filter(|x| x.segment_type == LOAD)
+    }
+
+    pub fn is_executable(&self) -> bool {
+        self.header.magic == MAGIC
+            && self.header.class == CLASS_64
+            && self.header.data == LITTLE_ENDIAN
+            && self.header.file_type == EXECUTABLE
+            && self.header.machine == AMD64
     }
 }
@@ -0,0 +1,121 @@
+// NeurOS - Hobbyist operating system written in Rust.
+// Copyright (C) 2024 Theomund
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program. If not, see <https://www.gnu.org/licenses/>.
+
+use crate::memory;
+use crate::process::{self, State};
+use crate::scheduler;
+use crate::signal;
+use crate::sync::IrqMutex;
+use crate::syscall::Errno;
+use crate::timer::{self, Action, Timeout};
+use alloc::collections::{BTreeMap, VecDeque};
+use alloc::vec::Vec;
+use core::ptr;
+use core::time::Duration;
+use x86_64::registers::control::Cr3;
+use x86_64::structures::paging::PageTableFlags;
+
+type Key = (u64, u64);
+
+static WAITERS: IrqMutex<BTreeMap<Key, VecDeque<u64>>> =
+    IrqMutex::new("FUTEX_WAITERS", BTreeMap::new());
+
+fn get_key(address: u64, flags: PageTableFlags) -> Result<Key, Errno> {
+    if address % 4 != 0 {
+        return Err(Errno::Invalid);
+    }
+    if !memory::is_user_accessible(address, 4, flags) {
+        return Err(Errno::Fault);
+    }
+    Ok((Cr3::read().0.start_address().as_u64(), address))
+}
+
+fn is_queued(key: Key, pid: u64) -> bool {
+    WAITERS.lock().get(&key).is_some_and(|x| x.contains(&pid))
+}
+
+fn dequeue(key: Key, pid: u64) -> bool {
+    let mut waiters = WAITERS.lock();
+    let Some(queue) = waiters.get_mut(&key) else {
+        return false;
+    };
+    let queued = queue.contains(&pid);
+    queue.retain(|x| *x != pid);
+    if queue.is_empty() {
+        waiters.remove(&key);
+    }
+    queued
+}
+
+pub fn wait(address: u64, expected: u32, duration: Option<Duration>) -> Result<(), Errno> {
+    let key = get_key(address, PageTableFlags::empty())?;
+    let pid = process::get_current();
+    {
+        let mut waiters = WAITERS.lock();
+        let value = unsafe { ptr::read_volatile(address as *const u32) };
+        if value != expected {
+            return Err(Errno::Again);
+        }
+        waiters.entry(key).or_default().push_back(pid);
+    }
+    let timeout = Timeout::new(duration);
+    let timer = duration.map(|x| timer::schedule(x, None, Action::Wake(pid)));
+    scheduler::block_until(State::Blocked, || {
+        !is_queued(key, pid) || timeout.expired() || signal::is_pending()
+    });
+    if let Some(timer) = timer {
+        timer::cancel(timer);
+    }
+    if !dequeue(key, pid) {
+        Ok(())
+    } else if timeout.expired() {
+        Err(Errno::TimedOut)
+    } else {
+        Err(Errno::Interrupted)
+    }
+}
+
+pub fn wake(address: u64, count: u32) -> Result<u32, Errno> {
+    let key = get_key(address, PageTableFlags::empty())?;
+    let mut woken = Vec::new();
+    {
+        let mut waiters = WAITERS.lock();
+        if let Some(queue) = waiters.get_mut(&key) {
+            while woken.len() < count as usize {
+                let Some(pid) = queue.pop_front() else {
+                    break;
+                };
+                woken.push(pid);
+            }
+            if queue.is_empty() {
+                waiters.remove(&key);
+            }
+        }
+    }
+    for pid in &woken {
+        scheduler::wake(*pid);
+    }
+    Ok(u32::try_from(woken.len()).unwrap())
+}
+
+pub fn clear(address: u64) {
+    if get_key(address, PageTableFlags::WRITABLE).is_ok() {
+        unsafe {
+            ptr::write_volatile(address as *mut u32, 0);
+        }
+        wake(address, 1).ok();
+    }
+}
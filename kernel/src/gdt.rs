@@ -14,21 +14,54 @@
 // You should have received a copy of the GNU General Public License
 // along with this program. If not, see <https://www.gnu.org/licenses/>.
 
+use alloc::boxed::Box;
+use core::ptr;
 use spin::Lazy;
 use x86_64::instructions::tables::load_tss;
 use x86_64::registers::segmentation::{Segment, CS, DS, ES, FS, GS, SS};
 use x86_64::structures::gdt::{Descriptor, GlobalDescriptorTable, SegmentSelector};
 use x86_64::structures::tss::TaskStateSegment;
+use x86_64::VirtAddr;
 
-static TSS: TaskStateSegment = TaskStateSegment::new();
+pub const DOUBLE_FAULT_IST: u16 = 0;
+pub const NMI_IST: u16 = 1;
+pub const MACHINE_CHECK_IST: u16 = 2;
+pub const IST_COUNT: usize = 3;
+pub const IST_SIZE: usize = 0x4000;
 
-static GDT: Lazy<(GlobalDescriptorTable, Selectors)> = Lazy::new(|| {
+static mut BOOT_STACKS: [[u8; IST_SIZE]; IST_COUNT] = [[0; IST_SIZE]; IST_COUNT];
+
+static TSS: Lazy<TaskStateSegment> = Lazy::new(|| {
+    let start = VirtAddr::from_ptr(ptr::addr_of!(BOOT_STACKS));
+    let stacks = core::array::from_fn(|index| start + ((index + 1) * IST_SIZE) as u64);
+    create_tss(&stacks)
+});
+
+static GDT: Lazy<(GlobalDescriptorTable, Selectors)> = Lazy::new(|| create(&TSS));
+
+struct Selectors {
+    kernel_code: SegmentSelector,
+    kernel_data: SegmentSelector,
+    user_code: SegmentSelector,
+    user_data: SegmentSelector,
+    tss: SegmentSelector,
+}
+
+fn create_tss(stacks: &[VirtAddr; IST_COUNT]) -> TaskStateSegment {
+    let mut tss = TaskStateSegment::new();
+    for (index, stack) in stacks.iter().enumerate() {
+        tss.interrupt_stack_table[index] = *stack;
+    }
+    tss
+}
+
+fn create(tss: &'static TaskStateSegment) -> (GlobalDescriptorTable, Selectors) {
     let mut gdt = GlobalDescriptorTable::new();
     let kernel_code = gdt.append(Descriptor::kernel_code_segment());
     let kernel_data = gdt.append(Descriptor::kernel_data_segment());
     let user_data = gdt.append(Descriptor::user_data_segment());
     let user_code = gdt.append(Descriptor::user_code_segment());
-    let tss = gdt.append(Descriptor::tss_segment(&TSS));
+    let tss = gdt.append(Descriptor::tss_segment(tss));
     (
         gdt,
         Selectors {
@@ -39,14 +72,19 @@ static GDT: Lazy<(GlobalDescriptorTable, Selectors)> = Lazy::new(|| {
             tss,
         },
     )
-});
+}
 
-struct Selectors {
-    kernel_code: SegmentSelector,
-    kernel_data: SegmentSelector,
-    user_code: SegmentSelector,
-    user_data: SegmentSelector,
-    tss: SegmentSelector,
+fn load(gdt: &'static (GlobalDescriptorTable, Selectors)) {
+    gdt.0.load();
+    unsafe {
+        CS::set_reg(gdt.1.kernel_code);
+        DS::set_reg(gdt.1.kernel_data);
+        ES::set_reg(gdt.1.kernel_data);
+        FS::set_reg(gdt.1.kernel_data);
+        GS::set_reg(gdt.1.kernel_data);
+        SS::set_reg(gdt.1.kernel_data);
+        load_tss(gdt.1.tss);
+    }
 }
 
 pub fn get_kernel_code() -> SegmentSelector {
@@ -66,14 +104,12 @@ pub fn get_user_data() -> SegmentSelector {
 }
 
 pub fn initialize() {
-    GDT.0.load();
-    unsafe {
-        CS::set_reg(GDT.1.kernel_code);
-        DS::set_reg(GDT.1.kernel_data);
-        ES::set_reg(GDT.1.kernel_data);
-        FS::set_reg(GDT.1.kernel_data);
-        GS::set_reg(GDT.1.kernel_data);
-        SS::set_reg(GDT.1.kernel_data);
-        load_tss(GDT.1.tss);
-    }
+    load(&GDT);
+}
+
+pub fn initialize_cpu(stacks: &[VirtAddr; IST_COUNT]) -> *mut TaskStateSegment {
+    let tss = Box::into_raw(Box::new(create_tss(stacks)));
+    let gdt = Box::leak(Box::new(create(unsafe { &*tss })));
+    load(gdt);
+    tss
 }
@@ -0,0 +1,184 @@
+// NeurOS - Hobbyist operating system written in Rust.
+// Copyright (C) 2024 Theomund
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program. If not, see <https://www.gnu.org/licenses/>.
+
+use crate::acpi::ACPI;
+use crate::logger::{Level, LOGGER};
+use crate::timer::{ClockEvent, ClockSource};
+use crate::{debug, memory};
+use acpi::HpetInfo;
+use alloc::format;
+use core::ptr;
+use spin::Once;
+use x86_64::{PhysAddr, VirtAddr};
+
+const FEMTOSECONDS_PER_NANOSECOND: u64 = 1_000_000;
+const FEMTOSECONDS_PER_SECOND: u64 = 1_000_000_000_000_000;
+
+pub static HPET: Once<Hpet> = Once::new();
+
+#[repr(u64)]
+enum Register {
+    Capabilities = 0x000,
+    Configuration = 0x010,
+    Counter = 0x0F0,
+    TimerConfiguration = 0x100,
+    TimerComparator = 0x108,
+}
+
+const ENABLE: u64 = 1 << 0;
+const LEGACY_REPLACEMENT: u64 = 1 << 1;
+const LEGACY_CAPABLE: u64 = 1 << 15;
+
+const TIMER_INTERRUPT: u64 = 1 << 2;
+const TIMER_PERIODIC: u64 = 1 << 3;
+const TIMER_PERIODIC_CAPABLE: u64 = 1 << 4;
+const TIMER_SET_VALUE: u64 = 1 << 6;
+
+pub struct Hpet {
+    address: VirtAddr,
+    period: u64,
+    timers: u64,
+    legacy: bool,
+    periodic: bool,
+}
+
+impl Hpet {
+    pub fn new(info: &HpetInfo) -> Hpet {
+        let address = memory::map_mmio(PhysAddr::new(info.base_address as u64), 0x400);
+        let mut hpet = Hpet {
+            address,
+            period: 0,
+            timers: 0,
+            legacy: false,
+            periodic: false,
+        };
+        let capabilities = hpet.read(Register::Capabilities as u64);
+        hpet.period = capabilities >> 32;
+        hpet.timers = ((capabilities >> 8) & 0x1F) + 1;
+        hpet.legacy = capabilities & LEGACY_CAPABLE != 0;
+        hpet.periodic =
+            hpet.read(Register::TimerConfiguration as u64) & TIMER_PERIODIC_CAPABLE != 0;
+
+        let configuration = hpet.read(Register::Configuration as u64);
+        hpet.write(Register::Configuration as u64, configuration & !ENABLE);
+        hpet.write(Register::Counter as u64, 0);
+        hpet.write(Register::Configuration as u64, configuration | ENABLE);
+        hpet
+    }
+
+    fn read(&self, offset: u64) -> u64 {
+        let register = (self.address + offset).as_ptr::<u64>();
+        unsafe { ptr::read_volatile(register) }
+    }
+
+    fn write(&self, offset: u64, value: u64) {
+        let register = (self.address + offset).as_mut_ptr::<u64>();
+        unsafe {
+            ptr::write_volatile(register, value);
+        }
+    }
+
+    pub fn get_counter(&self) -> u64 {
+        self.read(Register::Counter as u64)
+    }
+
+    pub fn get_frequency(&self) -> u64 {
+        FEMTOSECONDS_PER_SECOND / self.period
+    }
+
+    fn nanoseconds_to_ticks(&self, nanoseconds: u64) -> u64 {
+        let ticks = u128::from(nanoseconds) * u128::from(FEMTOSECONDS_PER_NANOSECOND)
+            / u128::from(self.period);
+        u64::try_from(ticks).unwrap_or(u64::MAX).max(1)
+    }
+
+    fn route_legacy(&self) {
+        let configuration = self.read(Register::Configuration as u64);
+        self.write(
+            Register::Configuration as u64,
+            configuration | LEGACY_REPLACEMENT,
+        );
+    }
+}
+
+impl ClockSource for Hpet {
+    fn get_name(&self) -> &'static str {
+        "HPET"
+    }
+
+    fn get_rating(&self) -> u32 {
+        250
+    }
+
+    fn get_nanoseconds(&self) -> u64 {
+        let counter = u128::from(self.get_counter());
+        let nanoseconds =
+            counter * u128::from(self.period) / u128::from(FEMTOSECONDS_PER_NANOSECOND);
+        u64::try_from(nanoseconds).unwrap_or(u64::MAX)
+    }
+}
+
+impl ClockEvent for Hpet {
+    fn get_name(&self) -> &'static str {
+        "HPET"
+    }
+
+    fn get_rating(&self) -> u32 {
+        if self.legacy && self.periodic {
+            150
+        } else {
+            0
+        }
+    }
+
+    fn start_periodic(&self, frequency: u32) -> u64 {
+        let ticks = self.nanoseconds_to_ticks(1_000_000_000 / u64::from(frequency));
+        self.route_legacy();
+        self.write(
+            Register::TimerConfiguration as u64,
+            TIMER_INTERRUPT | TIMER_PERIODIC | TIMER_SET_VALUE,
+        );
+        self.write(Register::TimerComparator as u64, self.get_counter() + ticks);
+        self.write(Register::TimerComparator as u64, ticks);
+        ticks * self.period / FEMTOSECONDS_PER_NANOSECOND
+    }
+
+    fn start_oneshot(&self, nanoseconds: u64) {
+        let ticks = self.nanoseconds_to_ticks(nanoseconds);
+        self.route_legacy();
+        self.write(Register::TimerConfiguration as u64, TIMER_INTERRUPT);
+        self.write(Register::TimerComparator as u64, self.get_counter() + ticks);
+    }
+
+    fn stop(&self) {
+        let configuration = self.read(Register::TimerConfiguration as u64);
+        self.write(
+            Register::TimerConfiguration as u64,
+            configuration & !(TIMER_INTERRUPT | TIMER_PERIODIC),
+        );
+    }
+}
+
+pub fn initialize() -> Option<&'static Hpet> {
+    let info = ACPI.get_hpet()?;
+    let hpet = HPET.call_once(|| Hpet::new(info));
+    debug!(
+        "Initialized the HPET with {} timer(s) at {} Hz.",
+        hpet.timers,
+        hpet.get_frequency()
+    );
+    Some(hpet)
+}
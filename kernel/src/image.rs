@@ -15,11 +15,11 @@
 // along with this program. If not, see <https://www.gnu.org/licenses/>.
 
 use crate::initrd::INITRD;
+use crate::sync::IrqMutexGuard;
 use crate::vga::Vga;
 use alloc::vec;
 use alloc::vec::Vec;
 use core::str;
-use spin::MutexGuard;
 
 struct Header {
     width: usize,
@@ -121,7 +121,7 @@ impl Image {
         Image { header, data }
     }
 
-    pub fn draw(self, vga: &MutexGuard<Vga>) {
+    pub fn draw(self, vga: &IrqMutexGuard<Vga>) {
         let data = self.data;
         let height = self.header.height;
         let width = self.header.width;
@@ -14,56 +14,192 @@
 // You should have received a copy of the GNU General Public License
 // along with this program. If not, see <https://www.gnu.org/licenses/>.
 
+use crate::deferred::Work;
 use crate::keyboard::KEYBOARD;
 use crate::logger::{Level, LOGGER};
-use crate::scheduler::SCHEDULER;
+use crate::process::{self, Context};
 use crate::serial::SERIAL;
-use crate::shell::SERIAL_CONSOLE;
+use crate::signal::{SIGFPE, SIGILL, SIGSEGV};
+use crate::sync::IrqMutex;
 use crate::timer::TIMER;
-use crate::{debug, error, halt, warn};
+use crate::{
+    apic, cpu, debug, deferred, error, gdt, halt, ipi, keyboard, scheduler, serial, signal,
+    syscall, timer, warn,
+};
 use alloc::format;
+use core::arch::global_asm;
 use pic8259::ChainedPics;
-use spin::{Lazy, Mutex};
-use x86_64::structures::idt::{InterruptDescriptorTable, InterruptStackFrame, PageFaultErrorCode};
+use spin::Lazy;
+use x86_64::registers::control::Cr2;
+use x86_64::registers::segmentation::GS;
+use x86_64::structures::idt::{InterruptDescriptorTable, InterruptStackFrame};
+use x86_64::{PrivilegeLevel, VirtAddr};
 
 const PIC_1_OFFSET: u8 = 32;
 const PIC_2_OFFSET: u8 = PIC_1_OFFSET + 8;
 
 #[repr(u8)]
-enum InterruptIndex {
+pub enum InterruptIndex {
     Timer = PIC_1_OFFSET,
     Keyboard,
     Cascade,
     COM2,
     COM1,
+    ApicTimer = 0x30,
+    SystemCall = 0x80,
+    Yield = 0x81,
+    Reschedule = 0xFC,
+    Call = 0xFD,
+    Spurious = 0xFF,
+}
+
+global_asm!(
+    ".macro PUSH_CONTEXT",
+    "    push rbx",
+    "    push rcx",
+    "    push rdx",
+    "    push rsi",
+    "    push rdi",
+    "    push rbp",
+    "    push r8",
+    "    push r9",
+    "    push r10",
+    "    push r11",
+    "    push r12",
+    "    push r13",
+    "    push r14",
+    "    push r15",
+    ".endm",
+    ".macro POP_REGISTERS",
+    "    pop r15",
+    "    pop r14",
+    "    pop r13",
+    "    pop r12",
+    "    pop r11",
+    "    pop r10",
+    "    pop r9",
+    "    pop r8",
+    "    pop rbp",
+    "    pop rdi",
+    "    pop rsi",
+    "    pop rdx",
+    "    pop rcx",
+    "    pop rbx",
+    "    pop rax",
+    ".endm",
+    ".macro POP_CONTEXT",
+    "    POP_REGISTERS",
+    "    test qword ptr [rsp + 8], 3",
+    "    jz 2f",
+    "    swapgs",
+    "2:",
+    "    iretq",
+    ".endm",
+    ".macro SWITCH_ENTRY name, handler",
+    ".global \\name",
+    "\\name:",
+    "    test qword ptr [rsp + 8], 3",
+    "    jz 1f",
+    "    swapgs",
+    "1:",
+    "    push rax",
+    "    PUSH_CONTEXT",
+    "    mov rdi, rsp",
+    "    cld",
+    "    call \\handler",
+    "    mov rsp, rax",
+    "    POP_CONTEXT",
+    ".endm",
+    ".macro FAULT_ENTRY name, handler",
+    ".global \\name",
+    "\\name:",
+    "    test qword ptr [rsp + 16], 3",
+    "    jz 1f",
+    "    swapgs",
+    "1:",
+    "    xchg rax, [rsp]",
+    "    PUSH_CONTEXT",
+    "    mov rdi, rsp",
+    "    mov rsi, rax",
+    "    cld",
+    "    call \\handler",
+    "    mov rsp, rax",
+    "    POP_CONTEXT",
+    ".endm",
+    ".global system_call_entry",
+    "system_call_entry:",
+    "    swapgs",
+    "    mov gs:[16], rsp",
+    "    mov rsp, gs:[8]",
+    "    push 0",
+    "    push qword ptr gs:[16]",
+    "    push r11",
+    "    push 0",
+    "    push rcx",
+    "    push rax",
+    "    PUSH_CONTEXT",
+    "    mov rdi, rsp",
+    "    cld",
+    "    call system_call",
+    "    mov rsp, rax",
+    "    test qword ptr [rsp + 128], 3",
+    "    jz 3f",
+    "    mov rcx, [rsp + 96]",
+    "    cmp rcx, [rsp + 120]",
+    "    jne 3f",
+    "    shr rcx, 47",
+    "    jnz 3f",
+    "    mov r11, [rsp + 32]",
+    "    cmp r11, [rsp + 136]",
+    "    jne 3f",
+    "    POP_REGISTERS",
+    "    swapgs",
+    "    mov rsp, [rsp + 24]",
+    "    sysretq",
+    "3:",
+    "    POP_CONTEXT",
+    "SWITCH_ENTRY system_call_gate_entry, system_call_interrupt",
+    "SWITCH_ENTRY timer_entry, timer_interrupt",
+    "SWITCH_ENTRY apic_timer_entry, apic_timer_interrupt",
+    "SWITCH_ENTRY yield_entry, yield_interrupt",
+    "SWITCH_ENTRY reschedule_entry, reschedule_interrupt",
+    "SWITCH_ENTRY divide_error_entry, divide_error_interrupt",
+    "SWITCH_ENTRY invalid_opcode_entry, invalid_opcode_interrupt",
+    "SWITCH_ENTRY x87_floating_point_entry, x87_floating_point_interrupt",
+    "SWITCH_ENTRY simd_floating_point_entry, simd_floating_point_interrupt",
+    "FAULT_ENTRY general_protection_fault_entry, general_protection_fault_interrupt",
+    "FAULT_ENTRY page_fault_entry, page_fault_interrupt",
+);
+
+extern "C" {
+    fn system_call_entry();
+    fn system_call_gate_entry();
+    fn timer_entry();
+    fn apic_timer_entry();
+    fn yield_entry();
+    fn reschedule_entry();
+    fn divide_error_entry();
+    fn invalid_opcode_entry();
+    fn x87_floating_point_entry();
+    fn simd_floating_point_entry();
+    fn general_protection_fault_entry();
+    fn page_fault_entry();
 }
 
 static IDT: Lazy<InterruptDescriptorTable> = Lazy::new(|| {
     let mut idt = InterruptDescriptorTable::new();
-    idt.divide_error.set_handler_fn(divide_error_handler);
     idt.debug.set_handler_fn(debug_handler);
-    idt.non_maskable_interrupt.set_handler_fn(nmi_handler);
     idt.breakpoint.set_handler_fn(breakpoint_handler);
     idt.overflow.set_handler_fn(overflow_handler);
     idt.bound_range_exceeded.set_handler_fn(bound_range_handler);
-    idt.invalid_opcode.set_handler_fn(invalid_opcode_handler);
     idt.device_not_available
         .set_handler_fn(device_not_available_handler);
-    idt.double_fault.set_handler_fn(double_fault_handler);
     idt.invalid_tss.set_handler_fn(invalid_tss_handler);
     idt.segment_not_present
         .set_handler_fn(segment_not_present_handler);
     idt.stack_segment_fault
         .set_handler_fn(stack_segment_fault_handler);
-    idt.general_protection_fault
-        .set_handler_fn(general_protection_fault_handler);
-    idt.page_fault.set_handler_fn(page_fault_handler);
-    idt.x87_floating_point
-        .set_handler_fn(x87_floating_point_handler);
     idt.alignment_check.set_handler_fn(alignment_check_handler);
-    idt.machine_check.set_handler_fn(machine_check_handler);
-    idt.simd_floating_point
-        .set_handler_fn(simd_floating_point_handler);
     idt.virtualization.set_handler_fn(virtualization_handler);
     idt.cp_protection_exception
         .set_handler_fn(control_protection_handler);
@@ -72,141 +208,333 @@ static IDT: Lazy<InterruptDescriptorTable> = Lazy::new(|| {
     idt.vmm_communication_exception
         .set_handler_fn(vmm_communication_handler);
     idt.security_exception.set_handler_fn(security_handler);
-    idt[InterruptIndex::Timer as u8].set_handler_fn(timer_handler);
+    unsafe {
+        idt.divide_error
+            .set_handler_addr(VirtAddr::from_ptr(divide_error_entry as *const ()));
+        idt.invalid_opcode
+            .set_handler_addr(VirtAddr::from_ptr(invalid_opcode_entry as *const ()));
+        idt.general_protection_fault
+            .set_handler_addr(VirtAddr::from_ptr(
+                general_protection_fault_entry as *const (),
+            ));
+        idt.page_fault
+            .set_handler_addr(VirtAddr::from_ptr(page_fault_entry as *const ()));
+        idt.x87_floating_point
+            .set_handler_addr(VirtAddr::from_ptr(x87_floating_point_entry as *const ()));
+        idt.simd_floating_point
+            .set_handler_addr(VirtAddr::from_ptr(simd_floating_point_entry as *const ()));
+        idt.non_maskable_interrupt
+            .set_handler_fn(nmi_handler)
+            .set_stack_index(gdt::NMI_IST);
+        idt.double_fault
+            .set_handler_fn(double_fault_handler)
+            .set_stack_index(gdt::DOUBLE_FAULT_IST);
+        idt.machine_check
+            .set_handler_fn(machine_check_handler)
+            .set_stack_index(gdt::MACHINE_CHECK_IST);
+        idt[InterruptIndex::Timer as u8]
+            .set_handler_addr(VirtAddr::from_ptr(timer_entry as *const ()));
+        idt[InterruptIndex::ApicTimer as u8]
+            .set_handler_addr(VirtAddr::from_ptr(apic_timer_entry as *const ()));
+        idt[InterruptIndex::SystemCall as u8]
+            .set_handler_addr(VirtAddr::from_ptr(system_call_gate_entry as *const ()))
+            .set_privilege_level(PrivilegeLevel::Ring3);
+        idt[InterruptIndex::Yield as u8]
+            .set_handler_addr(VirtAddr::from_ptr(yield_entry as *const ()));
+        idt[InterruptIndex::Reschedule as u8]
+            .set_handler_addr(VirtAddr::from_ptr(reschedule_entry as *const ()));
+    }
     idt[InterruptIndex::Keyboard as u8].set_handler_fn(keyboard_handler);
     idt[InterruptIndex::COM1 as u8].set_handler_fn(serial_handler);
+    idt[InterruptIndex::Call as u8].set_handler_fn(call_handler);
+    idt[InterruptIndex::Spurious as u8].set_handler_fn(spurious_handler);
     idt
 });
 
-static PICS: Mutex<ChainedPics> =
-    Mutex::new(unsafe { ChainedPics::new(PIC_1_OFFSET, PIC_2_OFFSET) });
+static PICS: IrqMutex<ChainedPics> = IrqMutex::new("PICS", unsafe {
+    ChainedPics::new(PIC_1_OFFSET, PIC_2_OFFSET)
+});
 
-extern "x86-interrupt" fn divide_error_handler(frame: InterruptStackFrame) {
-    error!("Division error was thrown: {frame:?}");
+#[no_mangle]
+extern "C" fn divide_error_interrupt(context: u64) -> u64 {
+    fault(context, SIGFPE, "Division error was thrown")
 }
 
 extern "x86-interrupt" fn debug_handler(frame: InterruptStackFrame) {
+    swap_gs(&frame);
     debug!("Debug exception was thrown: {frame:?}");
+    swap_gs(&frame);
 }
 
 extern "x86-interrupt" fn nmi_handler(frame: InterruptStackFrame) {
+    swap_gs(&frame);
+    if ipi::is_halting() {
+        halt();
+    }
     error!("Non-Maskable Interrupt (NMI) was thrown: {frame:?}");
+    swap_gs(&frame);
 }
 
 extern "x86-interrupt" fn breakpoint_handler(frame: InterruptStackFrame) {
+    swap_gs(&frame);
     warn!("Breakpoint exception was thrown: {frame:?}");
+    swap_gs(&frame);
 }
 
 extern "x86-interrupt" fn overflow_handler(frame: InterruptStackFrame) {
+    swap_gs(&frame);
     error!("Overflow exception was thrown: {frame:?}");
+    swap_gs(&frame);
 }
 
 extern "x86-interrupt" fn bound_range_handler(frame: InterruptStackFrame) {
+    swap_gs(&frame);
     error!("Bound range exceeded exception was thrown: {frame:?}");
+    swap_gs(&frame);
 }
 
-extern "x86-interrupt" fn invalid_opcode_handler(frame: InterruptStackFrame) {
-    error!("Invalid opcode exception was thrown: {frame:?}");
+#[no_mangle]
+extern "C" fn invalid_opcode_interrupt(context: u64) -> u64 {
+    fault(context, SIGILL, "Invalid opcode exception was thrown")
 }
 
 extern "x86-interrupt" fn device_not_available_handler(frame: InterruptStackFrame) {
+    swap_gs(&frame);
     error!("Device not available exception was thrown: {frame:?}");
+    swap_gs(&frame);
 }
 
 extern "x86-interrupt" fn double_fault_handler(frame: InterruptStackFrame, code: u64) -> ! {
+    swap_gs(&frame);
     error!("Double fault was thrown (code 0x{code:x}): {frame:?}");
     halt();
 }
 
 extern "x86-interrupt" fn invalid_tss_handler(frame: InterruptStackFrame, code: u64) {
+    swap_gs(&frame);
     error!("Invalid TSS exception was thrown (code 0x{code:x}): {frame:?}");
+    swap_gs(&frame);
 }
 
 extern "x86-interrupt" fn segment_not_present_handler(frame: InterruptStackFrame, code: u64) {
+    swap_gs(&frame);
     error!("Segment not present exception was thrown (code 0x{code:x}): {frame:?}");
+    swap_gs(&frame);
 }
 
 extern "x86-interrupt" fn stack_segment_fault_handler(frame: InterruptStackFrame, code: u64) {
+    swap_gs(&frame);
     error!("Stack segment fault was thrown (code 0x{code:x}): {frame:?}");
+    swap_gs(&frame);
 }
 
-extern "x86-interrupt" fn general_protection_fault_handler(frame: InterruptStackFrame, code: u64) {
-    error!("General protection fault was thrown (code 0x{code:x}): {frame:?}");
+#[no_mangle]
+extern "C" fn general_protection_fault_interrupt(context: u64, code: u64) -> u64 {
+    let description = format!("General protection fault was thrown (code 0x{code:x})");
+    fault(context, SIGSEGV, &description)
 }
 
-extern "x86-interrupt" fn page_fault_handler(frame: InterruptStackFrame, code: PageFaultErrorCode) {
-    error!(
-        "Page fault was thrown (code 0x{:x}): {frame:?}",
-        code.bits()
+#[no_mangle]
+extern "C" fn page_fault_interrupt(context: u64, code: u64) -> u64 {
+    let description = format!(
+        "Page fault was thrown (code 0x{code:x}, address 0x{:x})",
+        Cr2::read_raw()
     );
+    fault(context, SIGSEGV, &description)
 }
 
-extern "x86-interrupt" fn x87_floating_point_handler(frame: InterruptStackFrame) {
-    error!("x87 floating point exception was thrown: {frame:?}");
+#[no_mangle]
+extern "C" fn x87_floating_point_interrupt(context: u64) -> u64 {
+    fault(context, SIGFPE, "x87 floating point exception was thrown")
 }
 
 extern "x86-interrupt" fn alignment_check_handler(frame: InterruptStackFrame, code: u64) {
+    swap_gs(&frame);
     error!("Alignment check exception was thrown (code 0x{code:x}): {frame:?}");
+    swap_gs(&frame);
 }
 
 extern "x86-interrupt" fn machine_check_handler(frame: InterruptStackFrame) -> ! {
+    swap_gs(&frame);
     error!("Machine check exception was thrown: {frame:?}");
     halt();
 }
 
-extern "x86-interrupt" fn simd_floating_point_handler(frame: InterruptStackFrame) {
-    error!("SIMD floating point exception was thrown: {frame:?}");
+#[no_mangle]
+extern "C" fn simd_floating_point_interrupt(context: u64) -> u64 {
+    fault(context, SIGFPE, "SIMD floating point exception was thrown")
 }
 
 extern "x86-interrupt" fn virtualization_handler(frame: InterruptStackFrame) {
+    swap_gs(&frame);
     error!("Virtualization exception was thrown: {frame:?}");
+    swap_gs(&frame);
 }
 
 extern "x86-interrupt" fn control_protection_handler(frame: InterruptStackFrame, code: u64) {
+    swap_gs(&frame);
     error!("Control protection exception was thrown (code 0x{code:x}): {frame:?}");
+    swap_gs(&frame);
 }
 
 extern "x86-interrupt" fn hypervisor_injection_handler(frame: InterruptStackFrame) {
+    swap_gs(&frame);
     error!("Hypervisor injection exception was thrown: {frame:?}");
+    swap_gs(&frame);
 }
 
 extern "x86-interrupt" fn vmm_communication_handler(frame: InterruptStackFrame, code: u64) {
+    swap_gs(&frame);
     error!("VMM communication exception was thrown (code 0x{code:x}): {frame:?}");
+    swap_gs(&frame);
 }
 
 extern "x86-interrupt" fn security_handler(frame: InterruptStackFrame, code: u64) {
+    swap_gs(&frame);
     error!("Security exception was thrown (code 0x{code:x}): {frame:?}");
+    swap_gs(&frame);
 }
 
-extern "x86-interrupt" fn timer_handler(_frame: InterruptStackFrame) {
-    TIMER.increment();
-    SCHEDULER.lock().tick();
+#[no_mangle]
+extern "C" fn timer_interrupt(context: u64) -> u64 {
+    enter();
+    let context = tick(context);
 
     unsafe {
         PICS.lock()
             .notify_end_of_interrupt(InterruptIndex::Timer as u8);
     }
+    leave();
+    signal::deliver(context)
+}
+
+#[no_mangle]
+extern "C" fn system_call(context: u64) -> u64 {
+    let registers = unsafe { &mut *(context as *mut Context) };
+    registers.cs = u64::from(gdt::get_user_code().0);
+    registers.ss = u64::from(gdt::get_user_data().0);
+    system_call_interrupt(context)
+}
+
+#[no_mangle]
+extern "C" fn system_call_interrupt(context: u64) -> u64 {
+    syscall::dispatch(context);
+    signal::deliver(context)
 }
 
-extern "x86-interrupt" fn keyboard_handler(_frame: InterruptStackFrame) {
-    KEYBOARD.lock().interpret();
+#[no_mangle]
+extern "C" fn yield_interrupt(context: u64) -> u64 {
+    let context = cpu::current().get_scheduler().lock().yield_now(context);
+    signal::deliver(context)
+}
+
+#[no_mangle]
+extern "C" fn reschedule_interrupt(context: u64) -> u64 {
+    enter();
+    apic::end_of_interrupt();
+    let context = cpu::current().get_scheduler().lock().reschedule(context);
+    leave();
+    signal::deliver(context)
+}
+
+#[no_mangle]
+extern "C" fn apic_timer_interrupt(context: u64) -> u64 {
+    enter();
+    let context = tick(context);
+    apic::end_of_interrupt();
+    leave();
+    signal::deliver(context)
+}
+
+extern "x86-interrupt" fn call_handler(frame: InterruptStackFrame) {
+    swap_gs(&frame);
+    enter();
+    ipi::run();
+    apic::end_of_interrupt();
+    leave();
+    swap_gs(&frame);
+}
+
+extern "x86-interrupt" fn spurious_handler(_frame: InterruptStackFrame) {}
+
+extern "x86-interrupt" fn keyboard_handler(frame: InterruptStackFrame) {
+    swap_gs(&frame);
+    enter();
+    let scan_code = KEYBOARD.lock().read();
+    deferred::schedule(Work::new(keyboard::interpret, scan_code));
 
     unsafe {
         PICS.lock()
             .notify_end_of_interrupt(InterruptIndex::Keyboard as u8);
     }
+    leave();
+    swap_gs(&frame);
 }
 
-extern "x86-interrupt" fn serial_handler(_frame: InterruptStackFrame) {
-    SERIAL_CONSOLE
-        .lock()
-        .interpret(&mut SERIAL.lock())
-        .expect("Failed to interpret serial console input.");
+extern "x86-interrupt" fn serial_handler(frame: InterruptStackFrame) {
+    swap_gs(&frame);
+    enter();
+    let interrupted = SERIAL.lock().receive();
+    deferred::schedule(Work::new(serial::interpret, u8::from(interrupted)));
 
     unsafe {
         PICS.lock()
             .notify_end_of_interrupt(InterruptIndex::COM1 as u8);
     }
+    leave();
+    swap_gs(&frame);
+}
+
+fn fault(context: u64, signal: u32, description: &str) -> u64 {
+    let registers = unsafe { &*(context as *const Context) };
+    if !process::is_user(context) {
+        error!("{description} at 0x{:x}.", registers.rip);
+        return context;
+    }
+    let pid = process::get_current();
+    warn!("{description} by process #{pid} at 0x{:x}.", registers.rip);
+    signal::force(pid, signal);
+    signal::deliver(context)
+}
+
+fn tick(context: u64) -> u64 {
+    let cpu = cpu::current();
+    if cpu.is_bootstrap() {
+        TIMER.increment();
+        timer::expire();
+    }
+    cpu.get_statistics().count_tick();
+    deferred::notify();
+    scheduler::tick(context)
+}
+
+fn swap_gs(frame: &InterruptStackFrame) {
+    if frame.code_segment.rpl() == PrivilegeLevel::Ring3 {
+        unsafe {
+            GS::swap();
+        }
+    }
+}
+
+fn enter() {
+    cpu::enter_interrupt();
+    cpu::current().get_statistics().count_interrupt();
+}
+
+fn leave() {
+    cpu::leave_interrupt();
+}
+
+pub fn in_interrupt() -> bool {
+    cpu::get_nesting() != 0
+}
+
+pub fn get_system_call_entry() -> VirtAddr {
+    VirtAddr::from_ptr(system_call_entry as *const ())
+}
+
+pub fn initialize_cpu() {
+    IDT.load();
 }
 
 pub fn initialize() {
@@ -0,0 +1,94 @@
+// NeurOS - Hobbyist operating system written in Rust.
+// Copyright (C) 2024 Theomund
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program. If not, see <https://www.gnu.org/licenses/>.
+
+use crate::apic::LAPIC;
+use crate::cpu::{self, Cpu};
+use crate::interrupts::InterruptIndex;
+use alloc::sync::Arc;
+use core::hint;
+use core::ptr;
+use core::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+
+static HALTING: AtomicBool = AtomicBool::new(false);
+
+pub struct Call {
+    function: fn(u64),
+    argument: u64,
+    pending: Arc<AtomicUsize>,
+}
+
+pub fn call(cpus: &[&'static Cpu], function: fn(u64), argument: u64) {
+    let this = cpu::current();
+    let pending = Arc::new(AtomicUsize::new(0));
+    let mut local = false;
+    for cpu in cpus {
+        if ptr::eq(*cpu, this) {
+            local = true;
+            continue;
+        }
+        pending.fetch_add(1, Ordering::AcqRel);
+        cpu.get_calls().lock().push_back(Call {
+            function,
+            argument,
+            pending: pending.clone(),
+        });
+        if let Some(lapic) = LAPIC.get() {
+            lapic.send_ipi(cpu.get_apic_id(), InterruptIndex::Call as u8);
+        }
+    }
+    if local {
+        function(argument);
+    }
+    while pending.load(Ordering::Acquire) != 0 {
+        run();
+        hint::spin_loop();
+    }
+}
+
+pub fn run() {
+    let cpu = cpu::current();
+    loop {
+        let call = cpu.get_calls().lock().pop_front();
+        match call {
+            Some(call) => {
+                (call.function)(call.argument);
+                call.pending.fetch_sub(1, Ordering::AcqRel);
+            }
+            None => break,
+        }
+    }
+}
+
+pub fn reschedule(cpu: &Cpu) {
+    if ptr::eq(cpu, cpu::current()) {
+        return;
+    }
+    if let Some(lapic) = LAPIC.get() {
+        lapic.send_ipi(cpu.get_apic_id(), InterruptIndex::Reschedule as u8);
+    }
+}
+
+pub fn is_halting() -> bool {
+    HALTING.load(Ordering::Acquire)
+}
+
+pub fn halt_all() {
+    if !HALTING.swap(true, Ordering::AcqRel) {
+        if let Some(lapic) = LAPIC.get() {
+            lapic.send_nmi_to_others();
+        }
+    }
+}
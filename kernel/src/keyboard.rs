@@ -14,32 +14,86 @@
 // You should have received a copy of the GNU General Public License
 // along with this program. If not, see <https://www.gnu.org/licenses/>.
 
-use crate::keyboard::ScanCode::Enter;
+use crate::blocking::{Condvar, Mutex};
 use crate::logger::{Level, LOGGER};
-use crate::shell::VGA_CONSOLE;
+use crate::process::KERNEL_PID;
+use crate::signal::{self, SIGINT};
+use crate::sync::IrqMutex;
 use crate::trace;
-use crate::vga::VGA;
 use alloc::format;
-use spin::{Lazy, Mutex};
+use core::sync::atomic::{AtomicU64, Ordering};
+use spin::Lazy;
 use x86_64::instructions::port::Port;
 
+const LOWER: &[u8] = b"\0\x1b1234567890-=\x08\tqwertyuiop[]\r\0asdfghjkl;'`\0\\zxcvbnm,./\0*\0 ";
+const UPPER: &[u8] = b"\0\x1b!@#$%^&*()_+\x08\tQWERTYUIOP{}\r\0ASDFGHJKL:\"~\0|ZXCVBNM<>?\0*\0 ";
+const RELEASE: u8 = 0x80;
+const INTERRUPT: u8 = b'c';
+const END_OF_TEXT: u8 = 0x03;
+const CAPACITY: usize = 256;
+
+static INPUT: Mutex<Input> = Mutex::new("KEYBOARD_INPUT", Input::new());
+static AVAILABLE: Condvar = Condvar::new("KEYBOARD_AVAILABLE");
+static FOREGROUND: AtomicU64 = AtomicU64::new(KERNEL_PID);
+
 pub enum ScanCode {
-    Enter = 0x1C,
+    LeftControl = 0x1D,
+    LeftShift = 0x2A,
+    RightShift = 0x36,
 }
 
-pub static KEYBOARD: Lazy<Mutex<Keyboard>> = Lazy::new(|| {
+pub static KEYBOARD: Lazy<IrqMutex<Keyboard>> = Lazy::new(|| {
     let keyboard = Keyboard::new();
-    Mutex::new(keyboard)
+    IrqMutex::new("KEYBOARD", keyboard)
 });
 
+struct Input {
+    items: [u8; CAPACITY],
+    head: usize,
+    length: usize,
+}
+
+impl Input {
+    const fn new() -> Input {
+        Input {
+            items: [0; CAPACITY],
+            head: 0,
+            length: 0,
+        }
+    }
+
+    fn push(&mut self, character: u8) {
+        if self.length == CAPACITY {
+            return;
+        }
+        let tail = (self.head + self.length) % CAPACITY;
+        self.items[tail] = character;
+        self.length += 1;
+    }
+
+    fn pop(&mut self) -> Option<u8> {
+        if self.length == 0 {
+            return None;
+        }
+        let character = self.items[self.head];
+        self.head = (self.head + 1) % CAPACITY;
+        self.length -= 1;
+        Some(character)
+    }
+}
+
 pub struct Keyboard {
     port: Port<u8>,
+    control: bool,
+    shift: bool,
 }
 
 impl Keyboard {
     pub fn new() -> Keyboard {
         Keyboard {
             port: Port::new(0x60),
+            control: false,
+            shift: false,
         }
     }
 
@@ -47,15 +101,47 @@ impl Keyboard {
         unsafe { self.port.read() }
     }
 
-    pub fn interpret(&mut self) {
-        let scan_code = self.read();
-        trace!("Received scan code (0x{scan_code:x}) from keyboard.");
+    fn translate(&mut self, scan_code: u8) -> Option<u8> {
+        let code = scan_code & !RELEASE;
+        if code == ScanCode::LeftShift as u8 || code == ScanCode::RightShift as u8 {
+            self.shift = scan_code & RELEASE == 0;
+            return None;
+        }
+        if code == ScanCode::LeftControl as u8 {
+            self.control = scan_code & RELEASE == 0;
+            return None;
+        }
+        if scan_code & RELEASE != 0 {
+            return None;
+        }
+        if self.control && LOWER.get(usize::from(code)) == Some(&INTERRUPT) {
+            return Some(END_OF_TEXT);
+        }
+        let map = if self.shift { UPPER } else { LOWER };
+        map.get(usize::from(code)).copied().filter(|x| *x != 0)
+    }
+}
 
-        if scan_code == Enter as u8 {
-            VGA_CONSOLE
-                .lock()
-                .display(&mut VGA.lock())
-                .expect("Failed to display VGA console.");
+pub fn interpret(scan_code: u8) {
+    trace!("Received scan code (0x{scan_code:x}) from keyboard.");
+    let character = KEYBOARD.lock().translate(scan_code);
+    match character {
+        Some(END_OF_TEXT) => {
+            signal::send(FOREGROUND.load(Ordering::Relaxed), SIGINT).ok();
+        }
+        Some(character) => {
+            INPUT.lock().push(character);
+            AVAILABLE.notify_all();
         }
+        None => {}
     }
 }
+
+pub fn set_foreground(pid: u64) {
+    FOREGROUND.store(pid, Ordering::Relaxed);
+}
+
+pub fn read() -> u8 {
+    let mut input = AVAILABLE.wait_while(INPUT.lock(), |x| x.length == 0);
+    input.pop().unwrap()
+}
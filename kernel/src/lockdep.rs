@@ -0,0 +1,315 @@
+// NeurOS - Hobbyist operating system written in Rust.
+// Copyright (C) 2024 Theomund
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program. If not, see <https://www.gnu.org/licenses/>.
+
+use crate::cpu;
+use crate::interrupts;
+use crate::serial::{Ports, Serial};
+use core::fmt::{self, Arguments};
+use core::panic::Location;
+use core::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use spin::Mutex;
+
+const MAX_CLASSES: usize = 32;
+const MAX_CPUS: usize = 64;
+const MAX_DEPTH: usize = 16;
+const UNREGISTERED: usize = usize::MAX;
+
+type Site = &'static Location<'static>;
+
+static GRAPH: Mutex<Graph> = Mutex::new(Graph::new());
+
+static BUSY: [AtomicBool; MAX_CPUS] = [const { AtomicBool::new(false) }; MAX_CPUS];
+
+pub struct Class {
+    id: AtomicUsize,
+}
+
+impl Class {
+    pub const fn new() -> Class {
+        Class {
+            id: AtomicUsize::new(UNREGISTERED),
+        }
+    }
+}
+
+#[derive(Clone, Copy)]
+struct Held {
+    class: usize,
+    site: Site,
+}
+
+#[derive(Clone, Copy)]
+struct Edge {
+    before: Site,
+    after: Site,
+}
+
+#[derive(Clone, Copy)]
+struct Usage {
+    interrupt: Option<Site>,
+    enabled: Option<Site>,
+    reported: bool,
+}
+
+enum Report {
+    Inversion {
+        held: Held,
+        acquired: Held,
+        path: [u8; MAX_CLASSES],
+        length: usize,
+    },
+    Context {
+        class: usize,
+        interrupt: Site,
+        enabled: Site,
+    },
+    Depth {
+        acquired: Held,
+    },
+    Overflow {
+        name: &'static str,
+    },
+}
+
+struct Graph {
+    names: [&'static str; MAX_CLASSES],
+    count: usize,
+    after: [u32; MAX_CLASSES],
+    edges: [[Option<Edge>; MAX_CLASSES]; MAX_CLASSES],
+    usage: [Usage; MAX_CLASSES],
+    held: [[Option<Held>; MAX_DEPTH]; MAX_CPUS],
+}
+
+impl Graph {
+    const fn new() -> Graph {
+        Graph {
+            names: [""; MAX_CLASSES],
+            count: 0,
+            after: [0; MAX_CLASSES],
+            edges: [[None; MAX_CLASSES]; MAX_CLASSES],
+            usage: [Usage {
+                interrupt: None,
+                enabled: None,
+                reported: false,
+            }; MAX_CLASSES],
+            held: [[None; MAX_DEPTH]; MAX_CPUS],
+        }
+    }
+
+    fn register(&mut self, class: &Class, name: &'static str) -> Option<usize> {
+        let id = class.id.load(Ordering::Relaxed);
+        if id != UNREGISTERED {
+            return Some(id);
+        }
+        let id = match self.names[..self.count].iter().position(|x| *x == name) {
+            Some(id) => id,
+            None if self.count < MAX_CLASSES => {
+                self.names[self.count] = name;
+                self.count += 1;
+                self.count - 1
+            }
+            None => return None,
+        };
+        class.id.store(id, Ordering::Relaxed);
+        Some(id)
+    }
+
+    fn path(&self, from: usize, to: usize) -> Option<([u8; MAX_CLASSES], usize)> {
+        let mut path = [0; MAX_CLASSES];
+        let mut visited = 0u32;
+        if self.search(from, to, &mut visited, &mut path, 0) {
+            let length = path.iter().take_while(|x| usize::from(**x) != to).count() + 1;
+            Some((path, length))
+        } else {
+            None
+        }
+    }
+
+    fn search(
+        &self,
+        current: usize,
+        target: usize,
+        visited: &mut u32,
+        path: &mut [u8; MAX_CLASSES],
+        depth: usize,
+    ) -> bool {
+        path[depth] = u8::try_from(current).unwrap();
+        if current == target {
+            return true;
+        }
+        *visited |= 1 << current;
+        for next in 0..self.count {
+            let reachable = self.after[current] & (1 << next) != 0;
+            if reachable
+                && *visited & (1 << next) == 0
+                && self.search(next, target, visited, path, depth + 1)
+            {
+                return true;
+            }
+        }
+        false
+    }
+
+    fn acquire(&mut self, cpu: usize, acquired: Held, enabled: bool) -> Option<Report> {
+        let mut report = None;
+        let class = acquired.class;
+
+        if interrupts::in_interrupt() {
+            self.usage[class].interrupt.get_or_insert(acquired.site);
+        } else if enabled {
+            self.usage[class].enabled.get_or_insert(acquired.site);
+        }
+        let usage = self.usage[class];
+        if let (Some(interrupt), Some(enabled), false) =
+            (usage.interrupt, usage.enabled, usage.reported)
+        {
+            self.usage[class].reported = true;
+            report = Some(Report::Context {
+                class,
+                interrupt,
+                enabled,
+            });
+        }
+
+        for held in self.held[cpu].iter().flatten().copied() {
+            if held.class == class || self.edges[held.class][class].is_some() {
+                continue;
+            }
+            if report.is_none() {
+                if let Some((path, length)) = self.path(class, held.class) {
+                    report = Some(Report::Inversion {
+                        held,
+                        acquired,
+                        path,
+                        length,
+                    });
+                }
+            }
+            self.after[held.class] |= 1 << class;
+            self.edges[held.class][class] = Some(Edge {
+                before: held.site,
+                after: acquired.site,
+            });
+        }
+
+        match self.held[cpu].iter_mut().find(|x| x.is_none()) {
+            Some(slot) => *slot = Some(acquired),
+            None => {
+                report.get_or_insert(Report::Depth { acquired });
+            }
+        }
+
+        report
+    }
+
+    fn release(&mut self, cpu: usize, class: usize) {
+        if let Some(slot) = self.held[cpu]
+            .iter_mut()
+            .rev()
+            .find(|x| x.is_some_and(|held| held.class == class))
+        {
+            *slot = None;
+        }
+    }
+
+    fn print(&self, report: &Report) -> fmt::Result {
+        let mut serial = Serial::new(Ports::COM1);
+        let mut print = |args: Arguments<'_>| fmt::write(&mut serial, args);
+        print(format_args!("\n[LOCKDEP] "))?;
+        match report {
+            Report::Inversion {
+                held,
+                acquired,
+                path,
+                length,
+            } => {
+                print(format_args!(
+                    "Possible ABBA deadlock detected between {} and {}.\n",
+                    self.names[held.class], self.names[acquired.class]
+                ))?;
+                print(format_args!(
+                    "  {} acquired at {}\n  {} acquired at {}\n",
+                    self.names[held.class], held.site, self.names[acquired.class], acquired.site
+                ))?;
+                print(format_args!("  Existing dependency chain:\n"))?;
+                for pair in path[..*length].windows(2) {
+                    let (from, to) = (usize::from(pair[0]), usize::from(pair[1]));
+                    if let Some(edge) = self.edges[from][to] {
+                        print(format_args!(
+                            "    {} (at {}) -> {} (at {})\n",
+                            self.names[from], edge.before, self.names[to], edge.after
+                        ))?;
+                    }
+                }
+            }
+            Report::Context {
+                class,
+                interrupt,
+                enabled,
+            } => {
+                print(format_args!(
+                    "Lock {} is taken both in and out of interrupt context.\n",
+                    self.names[*class]
+                ))?;
+                print(format_args!(
+                    "  In interrupt context at {interrupt}\n  With interrupts enabled at {enabled}\n"
+                ))?;
+            }
+            Report::Depth { acquired } => {
+                print(format_args!(
+                    "Exceeded the maximum lock depth of {MAX_DEPTH} while taking {} at {}.\n",
+                    self.names[acquired.class], acquired.site
+                ))?;
+            }
+            Report::Overflow { name } => {
+                print(format_args!(
+                    "Exceeded the maximum of {MAX_CLASSES} lock classes while registering {name}.\n"
+                ))?;
+            }
+        }
+        Ok(())
+    }
+}
+
+pub fn acquire(class: &Class, name: &'static str, site: Site, enabled: bool) {
+    let cpu = cpu::get_current_id() as usize % MAX_CPUS;
+    if BUSY[cpu].swap(true, Ordering::Acquire) {
+        return;
+    }
+    let mut graph = GRAPH.lock();
+    let report = match graph.register(class, name) {
+        Some(id) => graph.acquire(cpu, Held { class: id, site }, enabled),
+        None => Some(Report::Overflow { name }),
+    };
+    if let Some(report) = report {
+        graph.print(&report).ok();
+    }
+    drop(graph);
+    BUSY[cpu].store(false, Ordering::Release);
+}
+
+pub fn release(class: &Class) {
+    let id = class.id.load(Ordering::Relaxed);
+    if id == UNREGISTERED {
+        return;
+    }
+    let cpu = cpu::get_current_id() as usize % MAX_CPUS;
+    if BUSY[cpu].swap(true, Ordering::Acquire) {
+        return;
+    }
+    GRAPH.lock().release(cpu, id);
+    BUSY[cpu].store(false, Ordering::Release);
+}
@@ -15,17 +15,24 @@
 // along with this program. If not, see <https://www.gnu.org/licenses/>.
 
 use crate::ansi::{BLUE, DEFAULT, GREEN, ORANGE, PURPLE, RED, YELLOW};
+use crate::blocking::{RwLock, RwLockReadGuard};
+use crate::rtc::DateTime;
+use crate::sync::IrqMutex;
+use crate::timer::TIMER;
 use alloc::format;
 use alloc::string::String;
 use alloc::vec::Vec;
 use core::fmt::{Display, Formatter, Result};
-use spin::{Lazy, Mutex};
+use core::mem;
+use spin::Lazy;
 
-pub static LOGGER: Lazy<Mutex<Logger>> = Lazy::new(|| {
+pub static LOGGER: Lazy<IrqMutex<Logger>> = Lazy::new(|| {
     let logger = Logger::new();
-    Mutex::new(logger)
+    IrqMutex::new("LOGGER", logger)
 });
 
+static ARCHIVE: RwLock<Vec<Log>> = RwLock::new("LOG_ARCHIVE", Vec::new());
+
 #[macro_export]
 macro_rules! debug {
     ($($arg:tt)*) => {
@@ -86,6 +93,7 @@ pub enum Level {
 pub struct Log {
     level: Level,
     message: String,
+    timestamp: Option<DateTime>,
 }
 
 impl Display for Log {
@@ -98,6 +106,9 @@ impl Display for Log {
             Level::Trace => format!("{PURPLE}[TRACE]{DEFAULT}"),
             Level::Warn => format!("{YELLOW}[WARN]{DEFAULT}"),
         };
+        if let Some(timestamp) = self.timestamp {
+            write!(f, "{timestamp} ")?;
+        }
         write!(f, "{} {}", label, self.message)?;
         Ok(())
     }
@@ -113,11 +124,34 @@ impl Logger {
     }
 
     pub fn log(&mut self, level: Level, message: String) {
-        let log = Log { level, message };
+        let timestamp = TIMER
+            .get_realtime()
+            .map(|x| DateTime::from_unix(x.as_secs()));
+        let log = Log {
+            level,
+            message,
+            timestamp,
+        };
         self.logs.push(log);
     }
 
     pub fn get_logs(&self) -> &Vec<Log> {
         &self.logs
     }
+
+    fn take(&mut self) -> Vec<Log> {
+        mem::take(&mut self.logs)
+    }
+}
+
+/// Moves buffered messages into the archive, which can be read without
+/// spinning on the logger.
+pub fn get_archive() -> RwLockReadGuard<'static, Vec<Log>> {
+    let mut logs = LOGGER.lock().take();
+    ARCHIVE.write().append(&mut logs);
+    ARCHIVE.read()
+}
+
+pub fn try_get_archive() -> Option<RwLockReadGuard<'static, Vec<Log>>> {
+    ARCHIVE.try_read()
 }
@@ -24,28 +24,49 @@ extern crate alloc;
 
 mod acpi;
 mod ansi;
+mod apic;
+mod blocking;
+mod cpu;
+mod deferred;
 mod elf;
 mod font;
+mod futex;
 mod gdt;
+mod hpet;
 mod image;
 mod initrd;
 mod interrupts;
 mod intro;
+mod ipi;
 mod keyboard;
+#[cfg(feature = "lockdep")]
+mod lockdep;
 mod logger;
 mod memory;
+mod mlfq;
+mod pit;
 mod process;
+mod realtime;
+mod rlimit;
+mod rtc;
 mod scheduler;
 mod serial;
 mod shell;
+mod signal;
 mod smp;
+mod sync;
 mod syscall;
+mod thread;
 mod timer;
+mod tlb;
+mod tsc;
 mod userspace;
 mod vga;
+mod wait;
 
 use crate::ansi::{BOLD, NORMAL, RED};
 use crate::logger::{Level, LOGGER};
+use crate::timer::TIMER;
 use crate::vga::VGA;
 use alloc::format;
 use core::fmt::Write;
@@ -54,24 +75,38 @@ use x86_64::instructions;
 
 #[no_mangle]
 extern "C" fn kmain() -> ! {
+    cpu::initialize_early();
     gdt::initialize();
     memory::initialize();
     interrupts::initialize();
+    acpi::initialize();
+    timer::initialize();
+    rtc::initialize();
     smp::initialize();
     initrd::initialize();
     scheduler::initialize();
+    deferred::initialize();
     intro::initialize().expect("Failed to initialize intro.");
-    shell::initialize();
     userspace::initialize();
     tick();
 }
 
 #[panic_handler]
 fn panic(info: &PanicInfo) -> ! {
+    unsafe {
+        VGA.force_unlock();
+        LOGGER.force_unlock();
+    }
+    ipi::halt_all();
     let mut vga = VGA.lock();
     vga.clear();
     writeln!(vga, "{BOLD}{RED}[KERNEL PANIC]{NORMAL}\n").unwrap();
     fatal!("The kernel has panicked.\n{info}");
+    if let Some(archive) = logger::try_get_archive() {
+        for log in archive.iter() {
+            writeln!(vga, "{log}").unwrap();
+        }
+    }
     for log in LOGGER.lock().get_logs() {
         writeln!(vga, "{log}").unwrap();
     }
@@ -82,7 +117,14 @@ fn tick() -> ! {
     info!("The operating system has been successfully initialized.");
     instructions::interrupts::enable();
     loop {
-        instructions::hlt();
+        instructions::interrupts::disable();
+        deferred::notify();
+        TIMER.stop_tick();
+        instructions::interrupts::enable_and_hlt();
+        TIMER.restart_tick();
+        scheduler::release();
+        scheduler::balance();
+        thread::yield_now();
     }
 }
 
@@ -14,14 +14,24 @@
 // You should have received a copy of the GNU General Public License
 // along with this program. If not, see <https://www.gnu.org/licenses/>.
 
+use crate::sync::{IrqMutex, SpinLock};
+use crate::syscall::Errno;
+use crate::tlb::Shootdown;
+use alloc::vec::Vec;
+use core::alloc::{GlobalAlloc, Layout};
+use core::ptr::{self, NonNull};
+use core::sync::atomic::{AtomicU64, Ordering};
 use limine::memory_map::{Entry, EntryType};
 use limine::request::{HhdmRequest, MemoryMapRequest, StackSizeRequest};
-use linked_list_allocator::LockedHeap;
-use spin::{Lazy, Mutex};
+use linked_list_allocator::Heap;
+use spin::Lazy;
 use x86_64::registers::control::Cr3;
+use x86_64::structures::paging::frame::PhysFrameRangeInclusive;
+use x86_64::structures::paging::mapper::TranslateResult;
 use x86_64::structures::paging::page::PageRangeInclusive;
 use x86_64::structures::paging::{
-    FrameAllocator, Mapper, OffsetPageTable, Page, PageTableFlags, PhysFrame, Size4KiB,
+    FrameAllocator, FrameDeallocator, Mapper, OffsetPageTable, Page, PageTable, PageTableFlags,
+    PhysFrame, Size4KiB, Translate,
 };
 use x86_64::{PhysAddr, VirtAddr};
 
@@ -37,19 +47,20 @@ static MEMORY_MAP_REQUEST: MemoryMapRequest = MemoryMapRequest::new();
 #[link_section = ".requests"]
 static HHDM_REQUEST: HhdmRequest = HhdmRequest::new();
 
-static PHYSICAL_MANAGER: Lazy<Mutex<PhysicalManager>> = Lazy::new(|| {
+static PHYSICAL_MANAGER: Lazy<SpinLock<PhysicalManager>> = Lazy::new(|| {
     let manager = PhysicalManager::new();
-    Mutex::new(manager)
+    SpinLock::new("PHYSICAL_MANAGER", manager)
 });
 
-static VIRTUAL_MANAGER: Lazy<Mutex<VirtualManager>> = Lazy::new(|| {
+static VIRTUAL_MANAGER: Lazy<SpinLock<VirtualManager>> = Lazy::new(|| {
     let manager = VirtualManager::new();
-    Mutex::new(manager)
+    SpinLock::new("VIRTUAL_MANAGER", manager)
 });
 
 struct PhysicalManager {
     memory_map: &'static [&'static Entry],
     next: usize,
+    free: Option<PhysFrame>,
 }
 
 impl PhysicalManager {
@@ -58,6 +69,7 @@ impl PhysicalManager {
         PhysicalManager {
             memory_map: entries,
             next: 0,
+            free: None,
         }
     }
 
@@ -72,12 +84,26 @@ impl PhysicalManager {
 
 unsafe impl FrameAllocator<Size4KiB> for PhysicalManager {
     fn allocate_frame(&mut self) -> Option<PhysFrame<Size4KiB>> {
+        if let Some(frame) = self.free {
+            let next = unsafe { *physical_to_virtual(frame.start_address()).as_ptr::<u64>() };
+            self.free =
+                (next != FREE_END).then(|| PhysFrame::containing_address(PhysAddr::new(next)));
+            return Some(frame);
+        }
         let frame = self.usable_frames().nth(self.next);
         self.next += 1;
         frame
     }
 }
 
+impl FrameDeallocator<Size4KiB> for PhysicalManager {
+    unsafe fn deallocate_frame(&mut self, frame: PhysFrame<Size4KiB>) {
+        let next = self.free.map_or(FREE_END, |x| x.start_address().as_u64());
+        *physical_to_virtual(frame.start_address()).as_mut_ptr::<u64>() = next;
+        self.free = Some(frame);
+    }
+}
+
 struct VirtualManager {
     table: OffsetPageTable<'static>,
 }
@@ -94,6 +120,24 @@ impl VirtualManager {
         VirtualManager { table }
     }
 
+    fn map_frames(
+        &mut self,
+        start: Page,
+        frames: PhysFrameRangeInclusive,
+        flags: PageTableFlags,
+        manager: &mut PhysicalManager,
+    ) {
+        for (index, frame) in (0..).zip(frames) {
+            let page = start + index;
+            unsafe {
+                self.table
+                    .map_to(page, frame, flags, manager)
+                    .unwrap()
+                    .flush();
+            };
+        }
+    }
+
     fn allocate_pages(&mut self, range: PageRangeInclusive, manager: &mut PhysicalManager) {
         for page in range {
             let frame = manager.allocate_frame().unwrap();
@@ -108,12 +152,307 @@ impl VirtualManager {
     }
 }
 
+struct Allocator {
+    heap: IrqMutex<Heap>,
+}
+
+unsafe impl GlobalAlloc for Allocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        self.heap
+            .lock()
+            .allocate_first_fit(layout)
+            .map_or(ptr::null_mut(), NonNull::as_ptr)
+    }
+
+    unsafe fn dealloc(&self, pointer: *mut u8, layout: Layout) {
+        self.heap
+            .lock()
+            .deallocate(NonNull::new_unchecked(pointer), layout);
+    }
+}
+
 #[global_allocator]
-static ALLOCATOR: LockedHeap = LockedHeap::empty();
+static ALLOCATOR: Allocator = Allocator {
+    heap: IrqMutex::new("ALLOCATOR", Heap::empty()),
+};
 
 const HEAP_START: usize = 0x_4444_4444_0000;
 const HEAP_SIZE: usize = 4 * 1024 * 1024;
 
+const MMIO_START: u64 = 0x_5555_5555_0000;
+const USER_END: u64 = 0x_8000_0000_0000;
+const USER_ENTRIES: [usize; 2] = [0, 255];
+pub const PAGE_SIZE: usize = 4096;
+const FREE_END: u64 = u64::MAX;
+
+static MMIO_NEXT: AtomicU64 = AtomicU64::new(MMIO_START);
+
+pub fn get_hhdm_offset() -> u64 {
+    HHDM_REQUEST.get_response().unwrap().offset()
+}
+
+pub fn physical_to_virtual(address: PhysAddr) -> VirtAddr {
+    VirtAddr::new(get_hhdm_offset() + address.as_u64())
+}
+
+pub fn virtual_to_physical(address: u64) -> PhysAddr {
+    let offset = get_hhdm_offset();
+    if address >= offset {
+        PhysAddr::new(address - offset)
+    } else {
+        PhysAddr::new(address)
+    }
+}
+
+pub fn map_mmio(address: PhysAddr, size: u64) -> VirtAddr {
+    let first: PhysFrame = PhysFrame::containing_address(address);
+    let last: PhysFrame = PhysFrame::containing_address(address + (size - 1));
+    let frames = PhysFrame::range_inclusive(first, last);
+    let length = (last.start_address() - first.start_address()) + 4096;
+    let start = VirtAddr::new(MMIO_NEXT.fetch_add(length, Ordering::Relaxed));
+    let flags = PageTableFlags::PRESENT
+        | PageTableFlags::WRITABLE
+        | PageTableFlags::NO_CACHE
+        | PageTableFlags::WRITE_THROUGH
+        | PageTableFlags::NO_EXECUTE;
+    VIRTUAL_MANAGER.lock().map_frames(
+        Page::containing_address(start),
+        frames,
+        flags,
+        &mut PHYSICAL_MANAGER.lock(),
+    );
+    start + (address - first.start_address())
+}
+
+fn page_range(start: VirtAddr, size: u64) -> PageRangeInclusive {
+    let first = Page::containing_address(start);
+    let last = Page::containing_address(start + (size - 1));
+    Page::range_inclusive(first, last)
+}
+
+fn open(space: PhysFrame) -> OffsetPageTable<'static> {
+    let offset = VirtAddr::new(get_hhdm_offset());
+    let pointer = physical_to_virtual(space.start_address()).as_mut_ptr();
+    unsafe { OffsetPageTable::new(&mut *pointer, offset) }
+}
+
+fn zero_frame(frame: PhysFrame) {
+    let pointer = physical_to_virtual(frame.start_address()).as_mut_ptr::<u8>();
+    unsafe {
+        ptr::write_bytes(pointer, 0, PAGE_SIZE);
+    }
+}
+
+pub fn create_address_space() -> Result<PhysFrame, Errno> {
+    let manager = VIRTUAL_MANAGER.lock();
+    let frame = PHYSICAL_MANAGER
+        .lock()
+        .allocate_frame()
+        .ok_or(Errno::NoMemory)?;
+    zero_frame(frame);
+    let mut space = open(frame);
+    let table = space.level_4_table_mut();
+    for (index, entry) in manager.table.level_4_table().iter().enumerate() {
+        if !USER_ENTRIES.contains(&index) {
+            table[index] = entry.clone();
+        }
+    }
+    Ok(frame)
+}
+
+fn user_pages(start: u64, size: u64) -> Result<PageRangeInclusive, Errno> {
+    if size == 0 || start.checked_add(size).is_none_or(|x| x > USER_END) {
+        return Err(Errno::Fault);
+    }
+    let pages = page_range(VirtAddr::new(start), size);
+    if !pages
+        .clone()
+        .all(|x| USER_ENTRIES.contains(&usize::from(x.p4_index())))
+    {
+        return Err(Errno::Fault);
+    }
+    Ok(pages)
+}
+
+fn walk(
+    table: PhysFrame,
+    level: u8,
+    base: u64,
+    visit: &mut dyn FnMut(PhysFrame, Option<(Page, PageTableFlags)>) -> Result<(), Errno>,
+) -> Result<(), Errno> {
+    let pointer = physical_to_virtual(table.start_address()).as_ptr::<PageTable>();
+    let entries = unsafe { &*pointer };
+    for (index, entry) in entries.iter().enumerate() {
+        if level == 4 && !USER_ENTRIES.contains(&index) {
+            continue;
+        }
+        let Ok(frame) = entry.frame() else {
+            continue;
+        };
+        let address = base | (index as u64) << (12 + 9 * (u32::from(level) - 1));
+        if level == 1 {
+            let page = Page::containing_address(VirtAddr::new(address));
+            visit(frame, Some((page, entry.flags())))?;
+        } else {
+            walk(frame, level - 1, address, visit)?;
+            visit(frame, None)?;
+        }
+    }
+    Ok(())
+}
+
+pub fn copy_address_space(source: PhysFrame) -> Result<PhysFrame, Errno> {
+    let space = create_address_space()?;
+    let mut table = open(space);
+    let parent =
+        PageTableFlags::PRESENT | PageTableFlags::WRITABLE | PageTableFlags::USER_ACCESSIBLE;
+    let result = walk(source, 4, 0, &mut |frame, leaf| {
+        let Some((page, flags)) = leaf else {
+            return Ok(());
+        };
+        let mut manager = PHYSICAL_MANAGER.lock();
+        let copy = manager.allocate_frame().ok_or(Errno::NoMemory)?;
+        unsafe {
+            ptr::copy_nonoverlapping(
+                physical_to_virtual(frame.start_address()).as_ptr::<u8>(),
+                physical_to_virtual(copy.start_address()).as_mut_ptr::<u8>(),
+                PAGE_SIZE,
+            );
+            table.map_to_with_table_flags(page, copy, flags, parent, &mut *manager)
+        }
+        .map_err(|_| Errno::NoMemory)?
+        .ignore();
+        Ok(())
+    });
+    if let Err(errno) = result {
+        destroy_address_space(space);
+        return Err(errno);
+    }
+    Ok(space)
+}
+
+pub fn destroy_address_space(space: PhysFrame) {
+    let mut frames = Vec::new();
+    let _ = walk(space, 4, 0, &mut |frame, _| {
+        frames.push(frame);
+        Ok(())
+    });
+    frames.push(space);
+    let mut manager = PHYSICAL_MANAGER.lock();
+    for frame in frames {
+        unsafe { manager.deallocate_frame(frame) };
+    }
+}
+
+pub fn map_user(
+    space: PhysFrame,
+    start: u64,
+    size: u64,
+    flags: PageTableFlags,
+) -> Result<(), Errno> {
+    if size == 0 {
+        return Ok(());
+    }
+    let pages = user_pages(start, size)?;
+    let mut table = open(space);
+    let flags = flags | PageTableFlags::PRESENT | PageTableFlags::USER_ACCESSIBLE;
+    let parent =
+        PageTableFlags::PRESENT | PageTableFlags::WRITABLE | PageTableFlags::USER_ACCESSIBLE;
+    let mut manager = PHYSICAL_MANAGER.lock();
+    for page in pages {
+        if let TranslateResult::Mapped { flags: old, .. } = table.translate(page.start_address()) {
+            let execute = (old | flags) & !PageTableFlags::NO_EXECUTE;
+            let merged = execute | (old & flags & PageTableFlags::NO_EXECUTE);
+            unsafe { table.update_flags(page, merged) }
+                .map_err(|_| Errno::Fault)?
+                .ignore();
+            continue;
+        }
+        let frame = manager.allocate_frame().ok_or(Errno::NoMemory)?;
+        zero_frame(frame);
+        unsafe { table.map_to_with_table_flags(page, frame, flags, parent, &mut *manager) }
+            .map_err(|_| Errno::NoMemory)?
+            .ignore();
+    }
+    Ok(())
+}
+
+pub fn unmap_user(space: PhysFrame, start: u64, size: u64) -> Result<(), Errno> {
+    let pages = user_pages(start, size)?;
+    let mut table = open(space);
+    let mut shootdown = Shootdown::new(space);
+    let mut frames = Vec::new();
+    for page in pages {
+        if let Ok((frame, flush)) = table.unmap(page) {
+            flush.ignore();
+            shootdown.add(page);
+            frames.push(frame);
+        }
+    }
+    shootdown.flush();
+    let mut manager = PHYSICAL_MANAGER.lock();
+    for frame in frames {
+        unsafe { manager.deallocate_frame(frame) };
+    }
+    Ok(())
+}
+
+pub fn protect_user(
+    space: PhysFrame,
+    start: u64,
+    size: u64,
+    flags: PageTableFlags,
+) -> Result<(), Errno> {
+    let pages = user_pages(start, size)?;
+    let mut table = open(space);
+    let flags = flags | PageTableFlags::PRESENT;
+    if !pages.clone().all(|x| table.translate_page(x).is_ok()) {
+        return Err(Errno::NoMemory);
+    }
+    let mut shootdown = Shootdown::new(space);
+    for page in pages {
+        if let Ok(flush) = unsafe { table.update_flags(page, flags) } {
+            flush.ignore();
+            shootdown.add(page);
+        }
+    }
+    shootdown.flush();
+    Ok(())
+}
+
+pub fn copy_to_user(space: PhysFrame, start: u64, data: &[u8]) -> Result<(), Errno> {
+    let table = open(space);
+    let mut copied = 0;
+    while copied < data.len() {
+        let address = start + copied as u64;
+        let physical = table
+            .translate_addr(VirtAddr::try_new(address).map_err(|_| Errno::Fault)?)
+            .ok_or(Errno::Fault)?;
+        let offset = usize::try_from(address % PAGE_SIZE as u64).unwrap();
+        let length = (PAGE_SIZE - offset).min(data.len() - copied);
+        let pointer = physical_to_virtual(physical).as_mut_ptr::<u8>();
+        unsafe {
+            ptr::copy_nonoverlapping(data[copied..].as_ptr(), pointer, length);
+        }
+        copied += length;
+    }
+    Ok(())
+}
+
+pub fn is_user_accessible(start: u64, size: u64, flags: PageTableFlags) -> bool {
+    if size == 0 || start.checked_add(size).is_none_or(|x| x > USER_END) {
+        return false;
+    }
+    let table = open(Cr3::read().0);
+    let required = flags | PageTableFlags::PRESENT | PageTableFlags::USER_ACCESSIBLE;
+    page_range(VirtAddr::new(start), size).all(|page| {
+        matches!(
+            table.translate(page.start_address()),
+            TranslateResult::Mapped { flags, .. } if flags.contains(required)
+        )
+    })
+}
+
 pub fn initialize() {
     STACK_SIZE_REQUEST.get_response();
 
@@ -130,6 +469,6 @@ pub fn initialize() {
         .allocate_pages(page_range, &mut PHYSICAL_MANAGER.lock());
 
     unsafe {
-        ALLOCATOR.lock().init(HEAP_START as *mut u8, HEAP_SIZE);
+        ALLOCATOR.heap.lock().init(HEAP_START as *mut u8, HEAP_SIZE);
     }
 }
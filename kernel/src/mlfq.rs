@@ -0,0 +1,155 @@
+// NeurOS - Hobbyist operating system written in Rust.
+// Copyright (C) 2024 Theomund
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program. If not, see <https://www.gnu.org/licenses/>.
+
+use crate::scheduler::{Class, Policy, Priority};
+use crate::timer;
+use alloc::collections::{BTreeMap, VecDeque};
+use alloc::vec::Vec;
+
+const LEVELS: usize = 4;
+const BOOST_INTERVAL: u32 = 1000;
+
+pub struct Mlfq {
+    levels: [VecDeque<u64>; LEVELS],
+    entries: BTreeMap<u64, Entry>,
+    quantum: u32,
+    boost: u32,
+    remaining: u32,
+}
+
+struct Entry {
+    level: usize,
+    nice: i8,
+}
+
+impl Mlfq {
+    pub fn new(milliseconds: u32) -> Mlfq {
+        let boost = timer::milliseconds_to_ticks(BOOST_INTERVAL);
+        Mlfq {
+            levels: [const { VecDeque::new() }; LEVELS],
+            entries: BTreeMap::new(),
+            quantum: timer::milliseconds_to_ticks(milliseconds),
+            boost,
+            remaining: boost,
+        }
+    }
+
+    fn get_ceiling(nice: i8) -> usize {
+        usize::try_from(nice.max(0)).unwrap() * LEVELS / 20
+    }
+
+    fn get_level(&self, pid: u64) -> usize {
+        self.entries.get(&pid).map_or(0, |x| x.level)
+    }
+
+    fn get_highest(&self) -> Option<usize> {
+        self.levels.iter().position(|x| !x.is_empty())
+    }
+}
+
+impl Policy for Mlfq {
+    fn get_name(&self) -> &'static str {
+        "MLFQ"
+    }
+
+    fn get_quantum(&self, pid: u64) -> u32 {
+        let Some(entry) = self.entries.get(&pid) else {
+            return self.quantum;
+        };
+        let ticks = self.quantum << entry.level;
+        let scale = u32::try_from(20 - i32::from(entry.nice)).unwrap();
+        (ticks * scale / 20).max(1)
+    }
+
+    fn handles(&self, priority: Priority) -> bool {
+        priority.class == Class::Normal
+    }
+
+    fn enqueue(&mut self, pid: u64, priority: Priority) {
+        let nice = priority.nice;
+        let ceiling = Mlfq::get_ceiling(nice);
+        let entry = self.entries.entry(pid).or_insert(Entry { level: 0, nice });
+        entry.nice = nice;
+        entry.level = entry.level.max(ceiling);
+        let level = entry.level;
+        if !self.levels.iter().any(|x| x.contains(&pid)) {
+            self.levels[level].push_back(pid);
+        }
+    }
+
+    fn dequeue(&mut self) -> Option<u64> {
+        let level = self.get_highest()?;
+        self.levels[level].pop_front()
+    }
+
+    fn remove(&mut self, pid: u64) {
+        self.entries.remove(&pid);
+        for level in &mut self.levels {
+            level.retain(|x| *x != pid);
+        }
+    }
+
+    fn contains(&self, pid: u64) -> bool {
+        self.levels.iter().any(|x| x.contains(&pid))
+    }
+
+    fn queued(&self) -> Vec<u64> {
+        self.levels
+            .iter()
+            .rev()
+            .flat_map(|x| x.iter().rev())
+            .copied()
+            .collect()
+    }
+
+    fn len(&self) -> usize {
+        self.levels.iter().map(VecDeque::len).sum()
+    }
+
+    fn is_empty(&self) -> bool {
+        self.levels.iter().all(VecDeque::is_empty)
+    }
+
+    fn has_pending(&self) -> bool {
+        false
+    }
+
+    fn expire(&mut self, pid: u64) {
+        if let Some(entry) = self.entries.get_mut(&pid) {
+            entry.level = (entry.level + 1).min(LEVELS - 1);
+        }
+    }
+
+    fn preempts(&self, pid: u64) -> bool {
+        self.get_highest().is_some_and(|x| x < self.get_level(pid))
+    }
+
+    fn tick(&mut self, _: u64) {
+        self.remaining -= 1;
+        if self.remaining != 0 {
+            return;
+        }
+        self.remaining = self.boost;
+        for entry in self.entries.values_mut() {
+            entry.level = Mlfq::get_ceiling(entry.nice);
+        }
+        let queued: VecDeque<u64> = self.levels.iter_mut().flat_map(core::mem::take).collect();
+        for pid in queued {
+            let level = self.get_level(pid);
+            self.levels[level].push_back(pid);
+        }
+    }
+}
@@ -0,0 +1,140 @@
+// NeurOS - Hobbyist operating system written in Rust.
+// Copyright (C) 2024 Theomund
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program. If not, see <https://www.gnu.org/licenses/>.
+
+use crate::timer::{ClockEvent, ClockSource, TIMER};
+use x86_64::instructions::port::Port;
+
+const BASE_FREQUENCY: u64 = 1_193_182;
+const MAXIMUM_COUNT: u64 = 0xFFFF;
+const NANOSECONDS_PER_SECOND: u64 = 1_000_000_000;
+
+pub static PIT: Pit = Pit::new();
+
+#[repr(u8)]
+enum Mode {
+    Interrupt = 0b0011_0000,
+    RateGenerator = 0b0011_0100,
+    Delay = 0b1011_0000,
+}
+
+pub struct Pit {
+    channel: u16,
+    command: u16,
+    delay: u16,
+    gate: u16,
+}
+
+impl Pit {
+    pub const fn new() -> Pit {
+        Pit {
+            channel: 0x40,
+            command: 0x43,
+            delay: 0x42,
+            gate: 0x61,
+        }
+    }
+
+    pub fn wait(&self, nanoseconds: u64) {
+        let mut remaining = to_count(nanoseconds).max(1);
+        while remaining > 0 {
+            let count = remaining.min(MAXIMUM_COUNT);
+            self.delay(u16::try_from(count).unwrap());
+            remaining -= count;
+        }
+    }
+
+    fn delay(&self, count: u16) {
+        let [low, high] = count.to_le_bytes();
+        let mut command: Port<u8> = Port::new(self.command);
+        let mut delay: Port<u8> = Port::new(self.delay);
+        let mut gate: Port<u8> = Port::new(self.gate);
+        unsafe {
+            let value = gate.read() & !0b10;
+            gate.write(value & !0b1);
+            command.write(Mode::Delay as u8);
+            delay.write(low);
+            delay.write(high);
+            gate.write(value | 0b1);
+            while gate.read() & 0b10_0000 == 0 {}
+            gate.write(value & !0b1);
+        }
+    }
+
+    fn program(&self, mode: Mode, count: Option<u16>) {
+        let mut command: Port<u8> = Port::new(self.command);
+        let mut channel: Port<u8> = Port::new(self.channel);
+        unsafe {
+            command.write(mode as u8);
+            if let Some(count) = count {
+                let [low, high] = count.to_le_bytes();
+                channel.write(low);
+                channel.write(high);
+            }
+        }
+    }
+}
+
+impl ClockSource for Pit {
+    fn get_name(&self) -> &'static str {
+        "PIT"
+    }
+
+    fn get_rating(&self) -> u32 {
+        100
+    }
+
+    fn get_nanoseconds(&self) -> u64 {
+        TIMER.get_elapsed() * TIMER.get_period()
+    }
+
+    fn is_tick_based(&self) -> bool {
+        true
+    }
+}
+
+impl ClockEvent for Pit {
+    fn get_name(&self) -> &'static str {
+        "PIT"
+    }
+
+    fn get_rating(&self) -> u32 {
+        100
+    }
+
+    fn start_periodic(&self, frequency: u32) -> u64 {
+        let divisor = (BASE_FREQUENCY / u64::from(frequency)).clamp(1, MAXIMUM_COUNT);
+        self.program(Mode::RateGenerator, Some(u16::try_from(divisor).unwrap()));
+        divisor * NANOSECONDS_PER_SECOND / BASE_FREQUENCY
+    }
+
+    /// Timeouts longer than one full count (about 55 ms) fire early; the idle
+    /// loop then re-arms the event for whatever time is left.
+    fn start_oneshot(&self, nanoseconds: u64) {
+        let count = to_count(nanoseconds).clamp(1, MAXIMUM_COUNT);
+        self.program(Mode::Interrupt, Some(u16::try_from(count).unwrap()));
+    }
+
+    fn stop(&self) {
+        self.program(Mode::Interrupt, None);
+    }
+}
+
+fn to_count(nanoseconds: u64) -> u64 {
+    match nanoseconds.checked_mul(BASE_FREQUENCY) {
+        Some(product) => product / NANOSECONDS_PER_SECOND,
+        None => (nanoseconds / NANOSECONDS_PER_SECOND).saturating_mul(BASE_FREQUENCY),
+    }
+}
@@ -14,55 +14,194 @@
 // You should have received a copy of the GNU General Public License
 // along with this program. If not, see <https://www.gnu.org/licenses/>.
 
+use crate::logger::{Level, LOGGER};
+use crate::rlimit::Limits;
+use crate::scheduler::{Class, Priority};
+use crate::signal::{self, Signals, SIGCHLD, SIGKILL};
+use crate::sync::IrqMutex;
+use crate::syscall::Errno;
+use crate::timer::TIMER;
+use crate::{cpu, debug, futex, gdt, realtime, scheduler, thread, timer};
+use alloc::boxed::Box;
+use alloc::collections::BTreeMap;
+use alloc::format;
 use alloc::string::{String, ToString};
+use alloc::vec;
+use alloc::vec::Vec;
+use core::fmt::{self, Display, Formatter};
+use core::mem;
+use core::ptr;
+use spin::Lazy;
+use x86_64::instructions::interrupts;
+use x86_64::registers::control::Cr3;
+use x86_64::structures::paging::PhysFrame;
 
-#[derive(Clone)]
+pub const KERNEL_PID: u64 = 0;
+pub const INIT_PID: u64 = 1;
+
+const FIRST_PID: u64 = 2;
+const MAXIMUM_PID: u64 = 32768;
+const STACK_SIZE: usize = 0x4000;
+const INTERRUPT_FLAG: u64 = 1 << 9;
+
+pub static PROCESSES: Lazy<IrqMutex<Table>> = Lazy::new(|| {
+    let table = Table::new();
+    IrqMutex::new("PROCESSES", table)
+});
+
+#[derive(Clone, Copy)]
 pub enum State {
     Running,
+    Ready,
+    Blocked,
+    Sleeping,
     Stopped,
+    Zombie,
+}
+
+impl Display for State {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            State::Running => "Running",
+            State::Ready => "Ready",
+            State::Blocked => "Blocked",
+            State::Sleeping => "Sleeping",
+            State::Stopped => "Stopped",
+            State::Zombie => "Zombie",
+        };
+        f.pad(name)
+    }
 }
 
 #[derive(Clone, Default)]
 #[repr(C)]
 pub struct Context {
-    rax: u64,
-    rbx: u64,
-    rcx: u64,
-    rdx: u64,
-    rsi: u64,
-    rdi: u64,
-    rsp: u64,
-    rbp: u64,
-    r8: u64,
-    r9: u64,
-    r10: u64,
-    r11: u64,
-    r12: u64,
-    r13: u64,
-    r14: u64,
-    r15: u64,
-    rip: u64,
-    rflags: u64,
+    pub r15: u64,
+    pub r14: u64,
+    pub r13: u64,
+    pub r12: u64,
+    pub r11: u64,
+    pub r10: u64,
+    pub r9: u64,
+    pub r8: u64,
+    pub rbp: u64,
+    pub rdi: u64,
+    pub rsi: u64,
+    pub rdx: u64,
+    pub rcx: u64,
+    pub rbx: u64,
+    pub rax: u64,
+    pub rip: u64,
+    pub cs: u64,
+    pub rflags: u64,
+    pub rsp: u64,
+    pub ss: u64,
 }
 
-#[derive(Clone)]
 pub struct Process {
     id: u64,
-    context: Context,
+    parent: u64,
+    address_space: PhysFrame,
+    affinity: u64,
+    alarm: Option<u64>,
+    children: Vec<u64>,
+    context: u64,
+    cpu: u32,
+    clear_child_tid: u64,
+    exit_status: Option<i32>,
+    fs_base: u64,
+    kernel_stack: Option<Box<[u8]>>,
+    limits: Limits,
     name: String,
+    priority: Priority,
+    on_cpu: bool,
+    signals: Signals,
+    start_time: u64,
     state: State,
+    switches: u64,
+    system_time: u64,
+    thread_group: u64,
+    user_time: u64,
 }
 
 impl Process {
     pub fn new(id: u64, name: &str, state: State) -> Process {
         Process {
             id,
-            context: Context::default(),
+            parent: KERNEL_PID,
+            address_space: Cr3::read().0,
+            affinity: u64::MAX,
+            alarm: None,
+            children: Vec::new(),
+            context: 0,
+            cpu: cpu::current().get_id(),
+            clear_child_tid: 0,
+            exit_status: None,
+            fs_base: 0,
+            kernel_stack: None,
+            limits: Limits::default(),
             name: name.to_string(),
+            priority: Priority::default(),
+            on_cpu: matches!(state, State::Running),
+            signals: Signals::default(),
+            start_time: TIMER.get_nanoseconds(),
             state,
+            switches: 0,
+            system_time: 0,
+            thread_group: id,
+            user_time: 0,
         }
     }
 
+    pub fn spawn(id: u64, name: &str, entry: extern "C" fn(u64) -> !, argument: u64) -> Process {
+        let mut process = Process::new(id, name, State::Ready);
+        let stack = vec![0u8; STACK_SIZE].into_boxed_slice();
+        let top = (stack.as_ptr() as u64 + STACK_SIZE as u64) & !0xF;
+        let context = Context {
+            rdi: argument,
+            rip: entry as usize as u64,
+            cs: u64::from(gdt::get_kernel_code().0),
+            rflags: INTERRUPT_FLAG,
+            rsp: top - 8,
+            ss: u64::from(gdt::get_kernel_data().0),
+            ..Context::default()
+        };
+        process.load(stack, context);
+        process
+    }
+
+    pub fn user(id: u64, name: &str, address_space: PhysFrame, entry: u64, stack: u64) -> Process {
+        let mut process = Process::new(id, name, State::Ready);
+        let context = Context {
+            rip: entry,
+            cs: u64::from(gdt::get_user_code().0),
+            rflags: INTERRUPT_FLAG,
+            rsp: stack,
+            ss: u64::from(gdt::get_user_data().0),
+            ..Context::default()
+        };
+        process.address_space = address_space;
+        process.load(vec![0u8; STACK_SIZE].into_boxed_slice(), context);
+        process
+    }
+
+    pub fn thread(id: u64, name: &str, context: Context) -> Process {
+        let mut process = Process::new(id, name, State::Ready);
+        let stack = vec![0u8; STACK_SIZE].into_boxed_slice();
+        process.load(stack, context);
+        process
+    }
+
+    fn load(&mut self, stack: Box<[u8]>, context: Context) {
+        let top = (stack.as_ptr() as u64 + STACK_SIZE as u64) & !0xF;
+        let address = top - 8 - mem::size_of::<Context>() as u64;
+        unsafe {
+            ptr::write(address as *mut Context, context);
+        }
+        self.context = address;
+        self.kernel_stack = Some(stack);
+    }
+
     pub fn get_id(&self) -> u64 {
         self.id
     }
@@ -71,11 +210,382 @@ impl Process {
         self.name.as_str()
     }
 
-    pub fn set_id(&mut self, id: u64) {
-        self.id = id;
+    pub fn get_parent(&self) -> u64 {
+        self.parent
+    }
+
+    pub fn get_priority(&self) -> Priority {
+        self.priority
+    }
+
+    pub fn get_address_space(&self) -> PhysFrame {
+        self.address_space
+    }
+
+    pub fn get_alarm(&self) -> Option<u64> {
+        self.alarm
+    }
+
+    pub fn get_affinity(&self) -> u64 {
+        self.affinity
+    }
+
+    pub fn get_context(&self) -> u64 {
+        self.context
+    }
+
+    pub fn get_cpu(&self) -> u32 {
+        self.cpu
+    }
+
+    pub fn get_fs_base(&self) -> u64 {
+        self.fs_base
+    }
+
+    pub fn get_limits(&self) -> &Limits {
+        &self.limits
+    }
+
+    pub fn get_limits_mut(&mut self) -> &mut Limits {
+        &mut self.limits
+    }
+
+    pub fn get_signals(&self) -> &Signals {
+        &self.signals
+    }
+
+    pub fn get_signals_mut(&mut self) -> &mut Signals {
+        &mut self.signals
+    }
+
+    pub fn get_start_time(&self) -> u64 {
+        self.start_time
+    }
+
+    pub fn get_state(&self) -> &State {
+        &self.state
+    }
+
+    pub fn get_switches(&self) -> u64 {
+        self.switches
+    }
+
+    pub fn get_system_time(&self) -> u64 {
+        self.system_time
+    }
+
+    pub fn get_thread_group(&self) -> u64 {
+        self.thread_group
+    }
+
+    pub fn get_user_time(&self) -> u64 {
+        self.user_time
+    }
+
+    pub fn get_kernel_stack_top(&self) -> Option<u64> {
+        self.kernel_stack
+            .as_ref()
+            .map(|x| (x.as_ptr() as u64 + x.len() as u64) & !0xF)
+    }
+
+    pub fn charge(&mut self, elapsed: u64, user: bool) {
+        if user {
+            self.user_time += elapsed;
+        } else {
+            self.system_time += elapsed;
+        }
+    }
+
+    pub fn count_switch(&mut self) {
+        self.switches += 1;
+    }
+
+    pub fn is_allowed(&self, cpu: u32) -> bool {
+        scheduler::is_allowed(self.affinity, cpu)
+    }
+
+    pub fn is_leader(&self) -> bool {
+        self.id == self.thread_group
+    }
+
+    pub fn is_on_cpu(&self) -> bool {
+        self.on_cpu
+    }
+
+    pub fn is_runnable(&self) -> bool {
+        matches!(self.state, State::Running | State::Ready)
+    }
+
+    pub fn is_waiting(&self) -> bool {
+        matches!(self.state, State::Blocked | State::Sleeping)
+    }
+
+    pub fn is_zombie(&self) -> bool {
+        matches!(self.state, State::Zombie)
+    }
+
+    pub fn set_address_space(&mut self, address_space: PhysFrame) {
+        self.address_space = address_space;
+    }
+
+    pub fn set_affinity(&mut self, affinity: u64) {
+        self.affinity = affinity;
+    }
+
+    pub fn set_alarm(&mut self, alarm: Option<u64>) {
+        self.alarm = alarm;
+    }
+
+    pub fn set_clear_child_tid(&mut self, address: u64) {
+        self.clear_child_tid = address;
+    }
+
+    pub fn set_context(&mut self, context: u64) {
+        self.context = context;
+    }
+
+    pub fn set_cpu(&mut self, cpu: u32) {
+        self.cpu = cpu;
+    }
+
+    pub fn set_fs_base(&mut self, fs_base: u64) {
+        self.fs_base = fs_base;
+    }
+
+    pub fn set_limits(&mut self, limits: Limits) {
+        self.limits = limits;
+    }
+
+    pub fn set_on_cpu(&mut self, on_cpu: bool) {
+        self.on_cpu = on_cpu;
+    }
+
+    pub fn set_parent(&mut self, parent: u64) {
+        self.parent = parent;
+    }
+
+    pub fn set_signals(&mut self, signals: Signals) {
+        self.signals = signals;
+    }
+
+    pub fn set_priority(&mut self, priority: Priority) {
+        self.priority = priority;
     }
 
     pub fn set_state(&mut self, state: State) {
         self.state = state;
     }
+
+    pub fn set_thread_group(&mut self, thread_group: u64) {
+        self.thread_group = thread_group;
+    }
+}
+
+pub struct Table {
+    processes: BTreeMap<u64, Process>,
+    next: u64,
+}
+
+impl Table {
+    fn new() -> Table {
+        Table {
+            processes: BTreeMap::new(),
+            next: FIRST_PID,
+        }
+    }
+
+    pub fn allocate(&mut self) -> Option<u64> {
+        for _ in FIRST_PID..MAXIMUM_PID {
+            let pid = self.next;
+            self.next = if pid + 1 >= MAXIMUM_PID {
+                FIRST_PID
+            } else {
+                pid + 1
+            };
+            if !self.processes.contains_key(&pid) {
+                return Some(pid);
+            }
+        }
+        None
+    }
+
+    pub fn insert(&mut self, process: Process) {
+        if let Some(parent) = self
+            .processes
+            .get_mut(&process.parent)
+            .filter(|_| process.is_leader())
+        {
+            parent.children.push(process.id);
+        }
+        self.processes.insert(process.id, process);
+    }
+
+    pub fn get(&self, pid: u64) -> Option<&Process> {
+        self.processes.get(&pid)
+    }
+
+    pub fn get_mut(&mut self, pid: u64) -> Option<&mut Process> {
+        self.processes.get_mut(&pid)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Process> {
+        self.processes.values()
+    }
+
+    pub fn count(&self) -> u64 {
+        self.processes.len() as u64
+    }
+
+    pub fn exit(&mut self, pid: u64, status: i32) {
+        let Some(process) = self.processes.get_mut(&pid) else {
+            return;
+        };
+        process.state = State::Zombie;
+        process.exit_status = Some(process.exit_status.unwrap_or(status));
+        if let Some(alarm) = process.alarm.take() {
+            timer::cancel(alarm);
+        }
+        realtime::admit(process.priority.class, Class::Normal);
+        let children = mem::take(&mut process.children);
+        debug!(
+            "Process #{pid} ({}) exited with status {status}.",
+            process.name
+        );
+        let adopter = if self.processes.contains_key(&INIT_PID) && pid != INIT_PID {
+            INIT_PID
+        } else {
+            KERNEL_PID
+        };
+        for child in children {
+            self.adopt(adopter, child);
+        }
+    }
+
+    pub fn terminate_group(&mut self, pid: u64, status: i32) -> Vec<u64> {
+        let Some(group) = self.processes.get(&pid).map(|x| x.thread_group) else {
+            return Vec::new();
+        };
+        if let Some(leader) = self.processes.get_mut(&group) {
+            leader.exit_status.get_or_insert(status);
+        }
+        self.processes
+            .values()
+            .filter(|x| x.thread_group == group && x.id != pid && !x.is_zombie())
+            .map(|x| x.id)
+            .collect()
+    }
+
+    fn is_finished(&self, leader: u64) -> bool {
+        self.processes
+            .get(&leader)
+            .is_some_and(|x| x.is_zombie() && x.kernel_stack.is_none())
+            && !self
+                .processes
+                .values()
+                .any(|x| x.thread_group == leader && x.id != leader)
+    }
+
+    fn adopt(&mut self, parent: u64, pid: u64) {
+        let Some(child) = self.processes.get_mut(&pid) else {
+            return;
+        };
+        child.parent = parent;
+        if parent == KERNEL_PID && self.is_finished(pid) {
+            self.processes.remove(&pid);
+        } else if let Some(adopter) = self.processes.get_mut(&parent) {
+            adopter.children.push(pid);
+        }
+    }
+
+    pub fn release(&mut self, pid: u64) -> Option<u64> {
+        let process = self.processes.get_mut(&pid)?;
+        process.kernel_stack = None;
+        let leader = process.thread_group;
+        if !process.is_leader() {
+            self.processes.remove(&pid);
+        }
+        if !self.is_finished(leader) {
+            return None;
+        }
+        let parent = self.processes.get(&leader)?.parent;
+        if parent == KERNEL_PID {
+            self.processes.remove(&leader);
+            if let Some(kernel) = self.processes.get_mut(&KERNEL_PID) {
+                kernel.children.retain(|x| *x != leader);
+            }
+            return None;
+        }
+        if let Some(parent) = self.processes.get_mut(&parent) {
+            parent.signals.raise(SIGCHLD);
+        }
+        Some(parent)
+    }
+
+    fn reap(&mut self, parent: u64, pid: Option<u64>) -> Option<(u64, i32)> {
+        let children = &self.processes.get(&parent)?.children;
+        let child = children
+            .iter()
+            .copied()
+            .find(|x| pid.map_or(true, |pid| pid == *x) && self.is_finished(*x))?;
+        let process = self.processes.remove(&child)?;
+        if let Some(parent) = self.processes.get_mut(&parent) {
+            parent.children.retain(|x| *x != child);
+        }
+        Some((child, process.exit_status.unwrap_or_default()))
+    }
+
+    fn has_child(&self, parent: u64, pid: Option<u64>) -> bool {
+        self.processes
+            .get(&parent)
+            .is_some_and(|x| x.children.iter().any(|x| pid.map_or(true, |pid| pid == *x)))
+    }
+}
+
+pub fn is_user(context: u64) -> bool {
+    let context = unsafe { &*(context as *const Context) };
+    context.cs & 3 == 3
+}
+
+pub fn get_current() -> u64 {
+    cpu::current().get_current()
+}
+
+pub fn exit(status: i32) -> ! {
+    let pid = get_current();
+    let address = PROCESSES
+        .lock()
+        .get_mut(pid)
+        .map_or(0, |x| mem::take(&mut x.clear_child_tid));
+    if address != 0 {
+        futex::clear(address);
+    }
+    interrupts::disable();
+    PROCESSES.lock().exit(pid, status);
+    thread::yield_now();
+    unreachable!("An exited process was scheduled again.");
+}
+
+pub fn exit_group(status: i32) -> ! {
+    let threads = PROCESSES.lock().terminate_group(get_current(), status);
+    for thread in threads {
+        signal::send(thread, SIGKILL).ok();
+    }
+    exit(status);
+}
+
+pub fn wait(pid: Option<u64>, block: bool) -> Result<Option<(u64, i32)>, Errno> {
+    let parent = get_current();
+    let mut result = Ok(None);
+    scheduler::block_until(State::Blocked, || {
+        let mut table = PROCESSES.lock();
+        if let Some(child) = table.reap(parent, pid) {
+            result = Ok(Some(child));
+        } else if !table.has_child(parent, pid) {
+            result = Err(Errno::NoChild);
+        } else {
+            return !block;
+        }
+        true
+    });
+    result
 }
@@ -0,0 +1,284 @@
+// NeurOS - Hobbyist operating system written in Rust.
+// Copyright (C) 2024 Theomund
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program. If not, see <https://www.gnu.org/licenses/>.
+
+use crate::logger::{Level, LOGGER};
+use crate::scheduler::{Class, Policy, Priority};
+use crate::timer::{self, TIMER};
+use crate::warn;
+use alloc::collections::{BTreeMap, VecDeque};
+use alloc::format;
+use alloc::vec::Vec;
+use core::sync::atomic::{AtomicU64, Ordering};
+
+pub const MAXIMUM_PRIORITY: u8 = 99;
+
+const ROUND_ROBIN_INTERVAL: u32 = 100;
+const WINDOW: u64 = 1_000_000_000;
+const LIMIT: u64 = 950_000_000;
+
+static BANDWIDTH: AtomicU64 = AtomicU64::new(0);
+
+pub struct Realtime {
+    queues: [VecDeque<u64>; MAXIMUM_PRIORITY as usize + 1],
+    deadlines: Vec<u64>,
+    entries: BTreeMap<u64, Entry>,
+    quantum: u32,
+    last: u64,
+    window: u64,
+    used: u64,
+    throttled: bool,
+}
+
+struct Entry {
+    class: Class,
+    budget: u64,
+    deadline: u64,
+    replenish: u64,
+    depleted: bool,
+    pending: bool,
+}
+
+impl Entry {
+    fn new(class: Class, now: u64) -> Entry {
+        let mut entry = Entry {
+            class,
+            budget: 0,
+            deadline: 0,
+            replenish: 0,
+            depleted: false,
+            pending: false,
+        };
+        entry.refill(now);
+        entry
+    }
+
+    fn refill(&mut self, now: u64) {
+        if let Class::Deadline {
+            runtime,
+            deadline,
+            period,
+        } = self.class
+        {
+            self.budget = runtime;
+            self.deadline = now.saturating_add(deadline);
+            self.replenish = now.saturating_add(period);
+            self.depleted = false;
+        }
+    }
+
+    fn get_priority(&self) -> Option<u8> {
+        match self.class {
+            Class::Fifo(priority) | Class::RoundRobin(priority) => Some(priority),
+            _ => None,
+        }
+    }
+}
+
+impl Realtime {
+    pub fn new() -> Realtime {
+        let now = TIMER.get_nanoseconds();
+        Realtime {
+            queues: [const { VecDeque::new() }; MAXIMUM_PRIORITY as usize + 1],
+            deadlines: Vec::new(),
+            entries: BTreeMap::new(),
+            quantum: timer::milliseconds_to_ticks(ROUND_ROBIN_INTERVAL),
+            last: now,
+            window: now,
+            used: 0,
+            throttled: false,
+        }
+    }
+
+    fn get_earliest(&self) -> Option<(usize, u64)> {
+        self.deadlines
+            .iter()
+            .enumerate()
+            .filter_map(|(index, pid)| Some((index, self.entries.get(pid)?.deadline)))
+            .min_by_key(|(_, deadline)| *deadline)
+    }
+
+    fn get_highest(&self) -> Option<u8> {
+        let index = self.queues.iter().rposition(|x| !x.is_empty())?;
+        u8::try_from(index).ok()
+    }
+
+    fn push(&mut self, pid: u64) {
+        let Some(entry) = self.entries.get(&pid) else {
+            return;
+        };
+        match entry.get_priority() {
+            Some(priority) => self.queues[usize::from(priority)].push_back(pid),
+            None => self.deadlines.push(pid),
+        }
+    }
+
+    fn charge(&mut self, pid: u64, elapsed: u64) {
+        let Some(entry) = self.entries.get_mut(&pid) else {
+            return;
+        };
+        self.used += elapsed;
+        if !self.throttled && self.used >= LIMIT {
+            self.throttled = true;
+            warn!("Throttled real-time processes after exceeding their share of the CPU.");
+        }
+        if matches!(entry.class, Class::Deadline { .. }) {
+            entry.budget = entry.budget.saturating_sub(elapsed);
+            entry.depleted = entry.budget == 0;
+        }
+    }
+
+    fn replenish(&mut self, now: u64) {
+        let mut ready = Vec::new();
+        for (pid, entry) in &mut self.entries {
+            if entry.depleted && now >= entry.replenish {
+                entry.refill(now);
+                if entry.pending {
+                    entry.pending = false;
+                    ready.push(*pid);
+                }
+            }
+        }
+        for pid in ready {
+            self.push(pid);
+        }
+    }
+}
+
+impl Policy for Realtime {
+    fn get_name(&self) -> &'static str {
+        "real-time"
+    }
+
+    fn get_quantum(&self, pid: u64) -> u32 {
+        match self.entries.get(&pid).map(|x| x.class) {
+            Some(Class::RoundRobin(_)) => self.quantum,
+            _ => u32::MAX,
+        }
+    }
+
+    fn handles(&self, priority: Priority) -> bool {
+        priority.class != Class::Normal
+    }
+
+    fn enqueue(&mut self, pid: u64, priority: Priority) {
+        let now = TIMER.get_nanoseconds();
+        let entry = self
+            .entries
+            .entry(pid)
+            .or_insert_with(|| Entry::new(priority.class, now));
+        if entry.class != priority.class {
+            *entry = Entry::new(priority.class, now);
+        } else if matches!(entry.class, Class::Deadline { .. }) && now >= entry.replenish {
+            entry.refill(now);
+        }
+        if entry.depleted {
+            entry.pending = true;
+            return;
+        }
+        self.push(pid);
+    }
+
+    fn dequeue(&mut self) -> Option<u64> {
+        if self.throttled {
+            return None;
+        }
+        if let Some((index, _)) = self.get_earliest() {
+            return Some(self.deadlines.swap_remove(index));
+        }
+        let priority = self.get_highest()?;
+        self.queues[usize::from(priority)].pop_front()
+    }
+
+    fn remove(&mut self, pid: u64) {
+        self.entries.remove(&pid);
+        self.deadlines.retain(|x| *x != pid);
+        for queue in &mut self.queues {
+            queue.retain(|x| *x != pid);
+        }
+    }
+
+    fn contains(&self, pid: u64) -> bool {
+        self.deadlines.contains(&pid) || self.queues.iter().any(|x| x.contains(&pid))
+    }
+
+    fn queued(&self) -> Vec<u64> {
+        let queues = self.queues.iter().flat_map(|x| x.iter().rev());
+        queues.chain(self.deadlines.iter()).copied().collect()
+    }
+
+    fn len(&self) -> usize {
+        self.deadlines.len() + self.queues.iter().map(VecDeque::len).sum::<usize>()
+    }
+
+    fn is_empty(&self) -> bool {
+        self.throttled || self.len() == 0
+    }
+
+    fn has_pending(&self) -> bool {
+        (self.throttled && self.len() != 0) || self.entries.values().any(|x| x.pending)
+    }
+
+    fn expire(&mut self, _: u64) {}
+
+    fn preempts(&self, pid: u64) -> bool {
+        let Some(entry) = self.entries.get(&pid) else {
+            return false;
+        };
+        if self.throttled || entry.depleted {
+            return true;
+        }
+        match entry.get_priority() {
+            Some(priority) => {
+                !self.deadlines.is_empty() || self.get_highest().is_some_and(|x| x > priority)
+            }
+            None => self
+                .get_earliest()
+                .is_some_and(|(_, deadline)| deadline < entry.deadline),
+        }
+    }
+
+    fn tick(&mut self, pid: u64) {
+        let now = TIMER.get_nanoseconds();
+        let elapsed = now.saturating_sub(self.last);
+        self.last = now;
+        if now.saturating_sub(self.window) >= WINDOW {
+            self.window = now;
+            self.used = 0;
+            self.throttled = false;
+        }
+        self.charge(pid, elapsed);
+        self.replenish(now);
+    }
+}
+
+fn get_bandwidth(class: Class) -> u64 {
+    match class {
+        Class::Deadline {
+            runtime, period, ..
+        } => runtime.saturating_mul(WINDOW) / period.max(1),
+        _ => 0,
+    }
+}
+
+pub fn admit(old: Class, new: Class) -> bool {
+    let (old, new) = (get_bandwidth(old), get_bandwidth(new));
+    BANDWIDTH
+        .fetch_update(Ordering::AcqRel, Ordering::Acquire, |x| {
+            let total = x.saturating_sub(old) + new;
+            (new <= old || total <= LIMIT).then_some(total)
+        })
+        .is_ok()
+}
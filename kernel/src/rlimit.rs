@@ -0,0 +1,153 @@
+// NeurOS - Hobbyist operating system written in Rust.
+// Copyright (C) 2024 Theomund
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program. If not, see <https://www.gnu.org/licenses/>.
+
+use crate::process::Table;
+use crate::signal::{SIGKILL, SIGXCPU};
+use crate::syscall::Errno;
+
+pub const INFINITY: u64 = u64::MAX;
+
+const COUNT: usize = 5;
+const NANOSECONDS: u64 = 1_000_000_000;
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+#[repr(u32)]
+pub enum Resource {
+    Cpu = 0,
+    Stack = 3,
+    Processes = 6,
+    // Stored for getrlimit and setrlimit, but not enforced until there is a
+    // file table.
+    Files = 7,
+    AddressSpace = 9,
+}
+
+impl Resource {
+    pub fn from_u32(resource: u32) -> Option<Resource> {
+        match resource {
+            0 => Some(Resource::Cpu),
+            3 => Some(Resource::Stack),
+            6 => Some(Resource::Processes),
+            7 => Some(Resource::Files),
+            9 => Some(Resource::AddressSpace),
+            _ => None,
+        }
+    }
+
+    pub fn get_name(self) -> &'static str {
+        match self {
+            Resource::Cpu => "cpu time",
+            Resource::Stack => "stack size",
+            Resource::Processes => "max user processes",
+            Resource::Files => "open files",
+            Resource::AddressSpace => "virtual memory",
+        }
+    }
+
+    fn index(self) -> usize {
+        match self {
+            Resource::Cpu => 0,
+            Resource::Stack => 1,
+            Resource::Processes => 2,
+            Resource::Files => 3,
+            Resource::AddressSpace => 4,
+        }
+    }
+}
+
+#[derive(Clone, Copy)]
+#[repr(C)]
+pub struct Limit {
+    pub current: u64,
+    pub maximum: u64,
+}
+
+impl Limit {
+    const fn new(current: u64, maximum: u64) -> Limit {
+        Limit { current, maximum }
+    }
+
+    pub fn allows(self, amount: u64) -> bool {
+        self.current == INFINITY || amount <= self.current
+    }
+}
+
+#[derive(Clone)]
+pub struct Limits {
+    limits: [Limit; COUNT],
+    warning: u64,
+}
+
+impl Default for Limits {
+    fn default() -> Limits {
+        Limits {
+            limits: [
+                Limit::new(INFINITY, INFINITY),
+                Limit::new(8 * 1024 * 1024, INFINITY),
+                Limit::new(1024, 1024),
+                Limit::new(1024, 4096),
+                Limit::new(INFINITY, INFINITY),
+            ],
+            warning: 0,
+        }
+    }
+}
+
+impl Limits {
+    pub fn get(&self, resource: Resource) -> Limit {
+        self.limits[resource.index()]
+    }
+
+    pub fn set(&mut self, resource: Resource, limit: Limit) -> Result<(), Errno> {
+        if limit.current > limit.maximum {
+            return Err(Errno::Invalid);
+        }
+        if limit.maximum > self.get(resource).maximum {
+            return Err(Errno::Permission);
+        }
+        self.limits[resource.index()] = limit;
+        if resource == Resource::Cpu {
+            self.warning = 0;
+        }
+        Ok(())
+    }
+}
+
+pub fn check_cpu(table: &mut Table, pid: u64) {
+    let Some(process) = table.get(pid) else {
+        return;
+    };
+    let limit = process.get_limits().get(Resource::Cpu);
+    if limit.current == INFINITY {
+        return;
+    }
+    let group = process.get_thread_group();
+    let elapsed: u64 = table
+        .iter()
+        .filter(|x| x.get_thread_group() == group)
+        .map(|x| x.get_user_time() + x.get_system_time())
+        .sum();
+    let seconds = elapsed / NANOSECONDS;
+    let Some(process) = table.get_mut(pid) else {
+        return;
+    };
+    if limit.maximum != INFINITY && seconds >= limit.maximum {
+        process.get_signals_mut().raise(SIGKILL);
+    } else if seconds >= limit.current && seconds >= process.get_limits().warning {
+        process.get_limits_mut().warning = seconds + 1;
+        process.get_signals_mut().raise(SIGXCPU);
+    }
+}
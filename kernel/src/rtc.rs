@@ -0,0 +1,254 @@
+// NeurOS - Hobbyist operating system written in Rust.
+// Copyright (C) 2024 Theomund
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program. If not, see <https://www.gnu.org/licenses/>.
+
+use crate::acpi::ACPI;
+use crate::info;
+use crate::logger::{Level, LOGGER};
+use crate::sync::IrqMutex;
+use crate::timer::TIMER;
+use alloc::format;
+use alloc::string::String;
+use core::fmt::{Display, Formatter};
+use x86_64::instructions::port::Port;
+
+const DAYS_PER_MONTH: [u64; 12] = [31, 28, 31, 30, 31, 30, 31, 31, 30, 31, 30, 31];
+const MONTHS: [&str; 12] = [
+    "Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec",
+];
+const WEEKDAYS: [&str; 7] = ["Thu", "Fri", "Sat", "Sun", "Mon", "Tue", "Wed"];
+
+const SECONDS_PER_MINUTE: u64 = 60;
+const SECONDS_PER_HOUR: u64 = SECONDS_PER_MINUTE * 60;
+const SECONDS_PER_DAY: u64 = SECONDS_PER_HOUR * 24;
+const UNIX_EPOCH_YEAR: u64 = 1970;
+
+pub static RTC: IrqMutex<Rtc> = IrqMutex::new("RTC", Rtc::new());
+
+#[repr(u8)]
+enum Register {
+    Seconds = 0x00,
+    Minutes = 0x02,
+    Hours = 0x04,
+    Day = 0x07,
+    Month = 0x08,
+    Year = 0x09,
+    StatusA = 0x0A,
+    StatusB = 0x0B,
+}
+
+const UPDATE_IN_PROGRESS: u8 = 1 << 7;
+const BINARY_MODE: u8 = 1 << 2;
+const HOUR_FORMAT: u8 = 1 << 1;
+const PM: u8 = 1 << 7;
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub struct DateTime {
+    year: u64,
+    month: u64,
+    day: u64,
+    hour: u64,
+    minute: u64,
+    second: u64,
+}
+
+impl DateTime {
+    fn is_leap_year(year: u64) -> bool {
+        (year % 4 == 0 && year % 100 != 0) || (year % 400 == 0)
+    }
+
+    fn days_in_month(year: u64, month: u64) -> u64 {
+        let index = usize::try_from(month - 1).unwrap();
+        if month == 2 && DateTime::is_leap_year(year) {
+            29
+        } else {
+            DAYS_PER_MONTH[index]
+        }
+    }
+
+    fn days_in_year(year: u64) -> u64 {
+        if DateTime::is_leap_year(year) {
+            366
+        } else {
+            365
+        }
+    }
+
+    pub fn from_unix(timestamp: u64) -> DateTime {
+        let mut days = timestamp / SECONDS_PER_DAY;
+        let mut seconds = timestamp % SECONDS_PER_DAY;
+
+        let mut year = UNIX_EPOCH_YEAR;
+        while days >= DateTime::days_in_year(year) {
+            days -= DateTime::days_in_year(year);
+            year += 1;
+        }
+
+        let mut month = 1;
+        while days >= DateTime::days_in_month(year, month) {
+            days -= DateTime::days_in_month(year, month);
+            month += 1;
+        }
+
+        let hour = seconds / SECONDS_PER_HOUR;
+        seconds %= SECONDS_PER_HOUR;
+        let minute = seconds / SECONDS_PER_MINUTE;
+        let second = seconds % SECONDS_PER_MINUTE;
+
+        DateTime {
+            year,
+            month,
+            day: days + 1,
+            hour,
+            minute,
+            second,
+        }
+    }
+
+    pub fn to_unix(self) -> u64 {
+        let years: u64 = (UNIX_EPOCH_YEAR..self.year)
+            .map(DateTime::days_in_year)
+            .sum();
+        let months: u64 = (1..self.month)
+            .map(|x| DateTime::days_in_month(self.year, x))
+            .sum();
+        let days = years + months + self.day - 1;
+        days * SECONDS_PER_DAY
+            + self.hour * SECONDS_PER_HOUR
+            + self.minute * SECONDS_PER_MINUTE
+            + self.second
+    }
+
+    pub fn get_weekday(self) -> &'static str {
+        let days = self.to_unix() / SECONDS_PER_DAY;
+        WEEKDAYS[usize::try_from(days % 7).unwrap()]
+    }
+
+    pub fn get_month_name(self) -> &'static str {
+        MONTHS[usize::try_from(self.month - 1).unwrap()]
+    }
+
+    pub fn to_date_string(self) -> String {
+        format!(
+            "{} {} {:2} {:02}:{:02}:{:02} UTC {}",
+            self.get_weekday(),
+            self.get_month_name(),
+            self.day,
+            self.hour,
+            self.minute,
+            self.second,
+            self.year
+        )
+    }
+}
+
+impl Display for DateTime {
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
+        write!(
+            f,
+            "{}-{:02}-{:02} {:02}:{:02}:{:02}",
+            self.year, self.month, self.day, self.hour, self.minute, self.second
+        )
+    }
+}
+
+pub struct Rtc {
+    address: Port<u8>,
+    data: Port<u8>,
+}
+
+impl Rtc {
+    pub const fn new() -> Rtc {
+        Rtc {
+            address: Port::new(0x70),
+            data: Port::new(0x71),
+        }
+    }
+
+    fn read(&mut self, register: u8) -> u8 {
+        unsafe {
+            self.address.write(0x80 | register);
+            self.data.read()
+        }
+    }
+
+    fn is_updating(&mut self) -> bool {
+        self.read(Register::StatusA as u8) & UPDATE_IN_PROGRESS != 0
+    }
+
+    fn read_raw(&mut self, century: u8) -> [u8; 7] {
+        while self.is_updating() {}
+        [
+            self.read(Register::Seconds as u8),
+            self.read(Register::Minutes as u8),
+            self.read(Register::Hours as u8),
+            self.read(Register::Day as u8),
+            self.read(Register::Month as u8),
+            self.read(Register::Year as u8),
+            if century == 0 { 0 } else { self.read(century) },
+        ]
+    }
+
+    pub fn read_date_time(&mut self, century: u8) -> DateTime {
+        let mut raw = self.read_raw(century);
+        loop {
+            let next = self.read_raw(century);
+            if next == raw {
+                break;
+            }
+            raw = next;
+        }
+
+        let status = self.read(Register::StatusB as u8);
+        let binary = status & BINARY_MODE != 0;
+        let decode = |value: u8| -> u64 {
+            if binary {
+                u64::from(value)
+            } else {
+                u64::from((value & 0x0F) + (value >> 4) * 10)
+            }
+        };
+
+        let [second, minute, hour, day, month, year, high] = raw;
+        let pm = hour & PM != 0;
+        let mut hour = decode(hour & !PM);
+        if status & HOUR_FORMAT == 0 {
+            hour %= 12;
+            if pm {
+                hour += 12;
+            }
+        }
+        let year = if century == 0 {
+            2000 + decode(year)
+        } else {
+            decode(high) * 100 + decode(year)
+        };
+
+        DateTime {
+            year,
+            month: decode(month),
+            day: decode(day),
+            hour,
+            minute: decode(minute),
+            second: decode(second),
+        }
+    }
+}
+
+pub fn initialize() {
+    let now = RTC.lock().read_date_time(ACPI.get_century());
+    TIMER.set_realtime(now.to_unix());
+    info!("Read the wall-clock time from the RTC: {now} UTC.");
+}
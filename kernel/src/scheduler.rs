@@ -14,81 +14,678 @@
 // You should have received a copy of the GNU General Public License
 // along with this program. If not, see <https://www.gnu.org/licenses/>.
 
+use crate::cpu::{self, Cpu};
 use crate::logger::{Level, LOGGER};
-use crate::process::Process;
-use crate::process::State;
-use crate::{debug, trace, warn};
-use alloc::collections::VecDeque;
+use crate::mlfq::Mlfq;
+use crate::process::{self, Context, Process, State, Table, KERNEL_PID, PROCESSES};
+use crate::realtime::Realtime;
+use crate::rlimit::{self, Resource};
+use crate::signal::{self, SIGALRM};
+use crate::sync::IrqMutex;
+use crate::syscall::Errno;
+use crate::timer::{self, Action, TIMER};
+use crate::{debug, deferred, ipi, memory, thread, trace};
+use alloc::boxed::Box;
 use alloc::format;
-use spin::{Lazy, Mutex};
+use alloc::vec;
+use alloc::vec::Vec;
+use core::mem;
+use core::time::Duration;
+use x86_64::instructions::interrupts;
+use x86_64::registers::control::Cr3;
+use x86_64::registers::model_specific::FsBase;
+use x86_64::VirtAddr;
 
-pub static SCHEDULER: Lazy<Mutex<Scheduler>> = Lazy::new(|| {
-    let scheduler = Scheduler::new(100);
-    Mutex::new(scheduler)
-});
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Class {
+    Normal,
+    Fifo(u8),
+    RoundRobin(u8),
+    Deadline {
+        runtime: u64,
+        deadline: u64,
+        period: u64,
+    },
+}
+
+#[derive(Clone, Copy)]
+pub struct Priority {
+    pub class: Class,
+    pub nice: i8,
+}
+
+impl Default for Priority {
+    fn default() -> Priority {
+        Priority {
+            class: Class::Normal,
+            nice: 0,
+        }
+    }
+}
+
+pub const LOAD_SHIFT: u32 = 11;
+
+const LOAD_ONE: u64 = 1 << LOAD_SHIFT;
+const LOAD_FACTORS: [u64; 3] = [1884, 2014, 2037];
+const LOAD_INTERVAL: u64 = 5;
+const BALANCE_INTERVAL: u64 = 100;
+const IMBALANCE: u64 = 2;
+const NANOSECONDS: u64 = 1_000_000_000;
+
+static LOAD_AVERAGE: IrqMutex<[u64; 3]> = IrqMutex::new("LOAD_AVERAGE", [0; 3]);
+
+pub trait Policy: Send {
+    fn get_name(&self) -> &'static str;
+    fn get_quantum(&self, pid: u64) -> u32;
+    fn handles(&self, priority: Priority) -> bool;
+    fn enqueue(&mut self, pid: u64, priority: Priority);
+    fn dequeue(&mut self) -> Option<u64>;
+    fn remove(&mut self, pid: u64);
+    fn contains(&self, pid: u64) -> bool;
+    fn queued(&self) -> Vec<u64>;
+    fn len(&self) -> usize;
+    fn is_empty(&self) -> bool;
+    fn has_pending(&self) -> bool;
+    fn expire(&mut self, pid: u64);
+    fn preempts(&self, pid: u64) -> bool;
+    fn tick(&mut self, pid: u64);
+}
 
 pub struct Scheduler {
-    queue: VecDeque<Process>,
-    quantum: u32,
+    policies: Vec<Box<dyn Policy>>,
+    current: u64,
+    idle: u64,
+    active: usize,
+    exited: Vec<u64>,
+    migrating: Vec<u64>,
+    last: u64,
+    user: u64,
+    system: u64,
     remaining: u32,
 }
 
 impl Scheduler {
-    pub fn new(quantum: u32) -> Scheduler {
-        debug!("Created process scheduler with a quantum of {quantum} ticks.");
+    pub fn new(milliseconds: u32) -> Scheduler {
+        let policies: Vec<Box<dyn Policy>> =
+            vec![Box::new(Realtime::new()), Box::new(Mlfq::new(milliseconds))];
+        let names: Vec<&str> = policies.iter().map(|x| x.get_name()).collect();
+        debug!(
+            "Created process scheduler with the {} policies and a base quantum of {milliseconds} ms.",
+            names.join(", ")
+        );
         Scheduler {
-            queue: VecDeque::new(),
-            quantum,
-            remaining: quantum,
+            active: policies.len(),
+            policies,
+            current: KERNEL_PID,
+            idle: KERNEL_PID,
+            exited: Vec::new(),
+            migrating: Vec::new(),
+            last: TIMER.get_nanoseconds(),
+            user: 0,
+            system: 0,
+            remaining: 0,
         }
     }
 
-    pub fn add(&mut self, process: Process) {
-        self.queue.push_back(process);
+    pub fn add(&mut self, pid: u64, priority: Priority) {
+        if pid == self.idle || self.policies.iter().any(|x| x.contains(pid)) {
+            return;
+        }
+        if let Some(policy) = self.policies.iter_mut().find(|x| x.handles(priority)) {
+            policy.enqueue(pid, priority);
+        }
     }
 
-    pub fn tick(&mut self) {
+    pub fn requeue(&mut self, pid: u64, priority: Priority) {
+        if self.policies.iter().any(|x| x.contains(pid)) {
+            self.remove(pid);
+            self.add(pid, priority);
+        }
+    }
+
+    fn remove(&mut self, pid: u64) {
+        for policy in &mut self.policies {
+            policy.remove(pid);
+        }
+    }
+
+    fn take(&mut self, pid: u64) -> bool {
+        let queued = self.policies.iter().any(|x| x.contains(pid));
+        self.remove(pid);
+        queued
+    }
+
+    fn steal(&mut self, table: &mut Table, cpu: u32) -> Option<(u64, Priority)> {
+        let pid = self
+            .policies
+            .iter()
+            .rev()
+            .flat_map(|x| x.queued())
+            .find(|x| table.get(*x).is_some_and(|x| x.is_allowed(cpu)))?;
+        self.remove(pid);
+        let process = table.get_mut(pid)?;
+        process.set_cpu(cpu);
+        Some((pid, process.get_priority()))
+    }
+
+    pub fn set_idle(&mut self, pid: u64) {
+        self.idle = pid;
+        self.current = pid;
+    }
+
+    pub fn is_idle(&self) -> bool {
+        self.policies.iter().all(|x| x.is_empty())
+    }
+
+    /// Reports work that becomes runnable on a later tick without being woken,
+    /// such as throttled or depleted real-time processes.
+    pub fn has_pending(&self) -> bool {
+        self.policies.iter().any(|x| x.has_pending())
+    }
+
+    pub fn get_load(&self) -> u64 {
+        let queued: usize = self.policies.iter().map(|x| x.len()).sum();
+        queued as u64 + u64::from(self.current != self.idle)
+    }
+
+    fn account(&mut self, context: u64) {
+        let now = TIMER.get_nanoseconds();
+        let elapsed = now.saturating_sub(self.last);
+        self.last = now;
+        if process::is_user(context) {
+            self.user += elapsed;
+        } else {
+            self.system += elapsed;
+        }
+    }
+
+    fn charge(&mut self, table: &mut Table) {
+        let (user, system) = (mem::take(&mut self.user), mem::take(&mut self.system));
+        let Some(process) = table.get_mut(self.current) else {
+            return;
+        };
+        let before = process.get_user_time() + process.get_system_time();
+        process.charge(user, true);
+        process.charge(system, false);
+        if before / NANOSECONDS != (before + user + system) / NANOSECONDS {
+            rlimit::check_cpu(table, self.current);
+        }
+    }
+
+    fn preempts(&self) -> bool {
+        if self.current == self.idle {
+            return true;
+        }
+        let higher = self.policies.iter().take(self.active);
+        higher.into_iter().any(|x| !x.is_empty())
+            || self
+                .policies
+                .get(self.active)
+                .is_some_and(|x| x.preempts(self.current))
+    }
+
+    pub fn tick(&mut self, context: u64) -> u64 {
+        self.account(context);
+        if self.user + self.system >= NANOSECONDS {
+            self.charge(&mut PROCESSES.lock());
+        }
+        for policy in &mut self.policies {
+            policy.tick(self.current);
+        }
+        let evicted = self
+            .policies
+            .get(self.active)
+            .is_some_and(|x| x.preempts(self.current));
+        if self.is_idle() && !evicted {
+            return context;
+        }
+        if self.preempts() {
+            return self.schedule(context, true);
+        }
+        self.remaining = self.remaining.saturating_sub(1);
         if self.remaining == 0 {
-            if self.queue.len() >= 2 {
-                self.schedule();
+            if let Some(policy) = self.policies.get_mut(self.active) {
+                policy.expire(self.current);
+            }
+            return self.schedule(context, true);
+        }
+        context
+    }
+
+    pub fn reschedule(&mut self, context: u64) -> u64 {
+        let allowed = PROCESSES
+            .lock()
+            .get(self.current)
+            .is_none_or(|x| x.is_allowed(cpu::current().get_id()));
+        if allowed && (self.is_idle() || !self.preempts()) {
+            return context;
+        }
+        self.schedule(context, true)
+    }
+
+    pub fn yield_now(&mut self, context: u64) -> u64 {
+        let runnable = PROCESSES
+            .lock()
+            .get(self.current)
+            .is_some_and(|x| matches!(x.get_state(), State::Running | State::Ready));
+        if self.is_idle() && (runnable || self.current == self.idle) {
+            return context;
+        }
+        self.schedule(context, false)
+    }
+
+    fn dequeue(&mut self) -> Option<u64> {
+        for (index, policy) in self.policies.iter_mut().enumerate() {
+            if let Some(pid) = policy.dequeue() {
+                self.active = index;
+                self.remaining = policy.get_quantum(pid);
+                return Some(pid);
+            }
+        }
+        self.active = self.policies.len();
+        None
+    }
+
+    fn release(&mut self, table: &mut Table) {
+        for pid in mem::take(&mut self.exited) {
+            if let Some(parent) = table.release(pid) {
+                self.wake(table, parent);
+            }
+        }
+    }
+
+    fn wake(&mut self, table: &mut Table, pid: u64) {
+        let Some(process) = table.get_mut(pid) else {
+            return;
+        };
+        if process.is_waiting() {
+            process.set_state(State::Ready);
+            if process.is_on_cpu() {
+                return;
+            }
+            if process.is_allowed(cpu::current().get_id()) {
+                self.add(pid, process.get_priority());
             } else {
-                warn!("Queue doesn't have enough processes to swap.");
+                self.migrating.push(pid);
             }
-            self.remaining = self.quantum;
-        } else {
-            self.remaining -= 1;
         }
     }
 
-    fn schedule(&mut self) {
-        let current = self.queue.pop_front().unwrap();
-        self.queue.push_back(current);
-        if let Some(back) = self.queue.back_mut() {
-            back.set_state(State::Stopped);
-            trace!("Stopped process #{} ({}).", back.get_id(), back.get_name());
+    fn schedule(&mut self, context: u64, preempted: bool) -> u64 {
+        let cpu = cpu::current();
+        let mut table = PROCESSES.lock();
+        self.release(&mut table);
+        self.account(context);
+        self.charge(&mut table);
+        let current = table.get_mut(self.current).unwrap();
+        current.set_context(context);
+        current.set_fs_base(FsBase::read().as_u64());
+        current.set_on_cpu(false);
+        match current.get_state() {
+            State::Zombie => {
+                trace!(
+                    "Exited process #{} ({}).",
+                    current.get_id(),
+                    current.get_name()
+                );
+                self.remove(self.current);
+                self.exited.push(self.current);
+            }
+            State::Blocked | State::Sleeping | State::Stopped if !preempted => {
+                trace!(
+                    "Blocked process #{} ({}).",
+                    current.get_id(),
+                    current.get_name()
+                );
+            }
+            _ if self.current != self.idle
+                && current.is_runnable()
+                && !current.is_allowed(cpu.get_id()) =>
+            {
+                current.set_state(State::Ready);
+                self.migrating.push(self.current);
+            }
+            _ => {
+                if current.is_runnable() {
+                    current.set_state(State::Ready);
+                }
+                let priority = current.get_priority();
+                self.add(self.current, priority);
+            }
+        }
+        let previous = self.current;
+        self.current = self.dequeue().unwrap_or(self.idle);
+        if self.current != previous {
+            cpu.get_statistics().count_switch();
+            if let Some(process) = table.get_mut(previous) {
+                process.count_switch();
+            }
         }
-        if let Some(front) = self.queue.front_mut() {
-            front.set_state(State::Running);
-            trace!(
-                "Started process #{} ({}).",
-                front.get_id(),
-                front.get_name()
-            );
+        cpu.set_current(self.current);
+        let next = table.get_mut(self.current).unwrap();
+        if next.is_runnable() {
+            next.set_state(State::Running);
         }
+        next.set_on_cpu(true);
+        next.set_cpu(cpu.get_id());
+        trace!("Started process #{} ({}).", next.get_id(), next.get_name());
+        if let Some(top) = next.get_kernel_stack_top() {
+            cpu.set_kernel_stack(top);
+        }
+        FsBase::write(VirtAddr::new(next.get_fs_base()));
+        let (frame, flags) = Cr3::read();
+        if next.get_address_space() != frame {
+            unsafe {
+                Cr3::write(next.get_address_space(), flags);
+            }
+            cpu.set_address_space(next.get_address_space());
+        }
+        next.get_context()
     }
+}
 
-    pub fn fork(&mut self) -> u64 {
-        let parent = self.queue.front().unwrap();
-        let mut child = parent.clone();
-        child.set_id((self.queue.len() + 1) as u64);
-        let pid = child.get_id();
-        self.add(child);
-        pid
+pub fn block_until<F: FnMut() -> bool>(state: State, mut condition: F) {
+    let pid = process::get_current();
+    loop {
+        if let Some(process) = PROCESSES.lock().get_mut(pid) {
+            process.set_state(state);
+        }
+        if condition() {
+            break;
+        }
+        thread::yield_now();
+    }
+    if let Some(process) = PROCESSES.lock().get_mut(pid) {
+        process.set_state(State::Running);
     }
 }
 
-pub fn initialize() {
-    SCHEDULER
+pub fn wake(pid: u64) {
+    let mut table = PROCESSES.lock();
+    let Some(process) = table.get_mut(pid) else {
+        return;
+    };
+    if !process.is_waiting() {
+        return;
+    }
+    process.set_state(State::Ready);
+    if process.is_on_cpu() {
+        return;
+    }
+    let (cpu, priority) = (process.get_cpu(), process.get_priority());
+    let allowed = process.is_allowed(cpu);
+    drop(table);
+    match cpu::get(cpu).filter(|_| allowed) {
+        Some(cpu) => {
+            cpu.get_scheduler().lock().add(pid, priority);
+            ipi::reschedule(cpu);
+        }
+        None => place(pid),
+    }
+}
+
+pub fn resume(pid: u64) {
+    let mut table = PROCESSES.lock();
+    let Some(process) = table.get_mut(pid) else {
+        return;
+    };
+    if !matches!(process.get_state(), State::Stopped) {
+        return;
+    }
+    process.set_state(State::Ready);
+    if process.is_on_cpu() {
+        return;
+    }
+    drop(table);
+    place(pid);
+}
+
+pub fn is_allowed(affinity: u64, cpu: u32) -> bool {
+    cpu < u64::BITS && affinity & (1 << cpu) != 0
+}
+
+pub fn select_cpu(affinity: u64) -> Option<&'static Cpu> {
+    let this = cpu::current().get_id();
+    cpu::get_cpus()
+        .into_iter()
+        .filter(|x| is_allowed(affinity, x.get_id()))
+        .min_by_key(|x| (x.get_scheduler().lock().get_load(), x.get_id() != this))
+}
+
+pub fn place(pid: u64) {
+    let table = PROCESSES.lock();
+    let Some(process) = table.get(pid) else {
+        return;
+    };
+    let (affinity, priority) = (process.get_affinity(), process.get_priority());
+    drop(table);
+    let Some(cpu) = select_cpu(affinity) else {
+        return;
+    };
+    if let Some(process) = PROCESSES.lock().get_mut(pid) {
+        process.set_cpu(cpu.get_id());
+    }
+    cpu.get_scheduler().lock().add(pid, priority);
+    ipi::reschedule(cpu);
+}
+
+pub fn migrate(pid: u64) {
+    let table = PROCESSES.lock();
+    let Some(process) = table.get(pid) else {
+        return;
+    };
+    let (id, running) = (process.get_cpu(), process.is_on_cpu());
+    if process.is_allowed(id) {
+        return;
+    }
+    drop(table);
+    let Some(cpu) = cpu::get(id) else {
+        return;
+    };
+    if running {
+        if pid == process::get_current() {
+            thread::yield_now();
+        } else {
+            ipi::reschedule(cpu);
+        }
+    } else if cpu.get_scheduler().lock().take(pid) {
+        place(pid);
+    }
+}
+
+pub fn balance() {
+    let this = cpu::current();
+    let migrating = mem::take(&mut this.get_scheduler().lock().migrating);
+    for pid in migrating {
+        place(pid);
+    }
+    let loads: Vec<(&'static Cpu, u64)> = cpu::get_cpus()
+        .into_iter()
+        .map(|x| (x, x.get_scheduler().lock().get_load()))
+        .collect();
+    let own = loads
+        .iter()
+        .find(|(x, _)| x.get_id() == this.get_id())
+        .map_or(0, |(_, load)| *load);
+    let Some((busiest, load)) = loads
+        .into_iter()
+        .filter(|(x, _)| x.get_id() != this.get_id())
+        .max_by_key(|(_, load)| *load)
+    else {
+        return;
+    };
+    if load < own + IMBALANCE {
+        return;
+    }
+    let stolen = busiest
+        .get_scheduler()
         .lock()
-        .add(Process::new(1, "kernel", State::Running));
+        .steal(&mut PROCESSES.lock(), this.get_id());
+    if let Some((pid, priority)) = stolen {
+        trace!(
+            "Migrated process #{pid} from CPU #{} to CPU #{}.",
+            busiest.get_id(),
+            this.get_id()
+        );
+        this.get_scheduler().lock().add(pid, priority);
+    }
+}
+
+pub fn tick(context: u64) -> u64 {
+    let cpu = cpu::current();
+    if cpu.get_statistics().get_ticks() % BALANCE_INTERVAL == 0 {
+        balance();
+    }
+    cpu.get_scheduler().lock().tick(context)
+}
+
+pub fn release() {
+    let mut scheduler = cpu::current().get_scheduler().lock();
+    let mut table = PROCESSES.lock();
+    scheduler.release(&mut table);
+}
+
+pub fn fork(context: u64) -> Result<u64, Errno> {
+    if !process::is_user(context) {
+        return Err(Errno::Invalid);
+    }
+    let parent = process::get_current();
+    let mut registers = unsafe { (*(context as *const Context)).clone() };
+    registers.rax = 0;
+    let table = PROCESSES.lock();
+    let process = table.get(parent).ok_or(Errno::NoSuchProcess)?;
+    let limits = process.get_limits().clone();
+    if !limits.get(Resource::Processes).allows(table.count() + 1) {
+        return Err(Errno::Again);
+    }
+    let source = process.get_address_space();
+    drop(table);
+    let space = memory::copy_address_space(source)?;
+    let mut table = PROCESSES.lock();
+    let Some((pid, process)) = table
+        .allocate()
+        .and_then(|pid| Some((pid, table.get(parent)?)))
+    else {
+        drop(table);
+        memory::destroy_address_space(space);
+        return Err(Errno::Again);
+    };
+    let mut child = Process::thread(pid, process.get_name(), registers);
+    child.set_address_space(space);
+    child.set_parent(process.get_thread_group());
+    child.set_affinity(process.get_affinity());
+    let mut priority = process.get_priority();
+    if matches!(priority.class, Class::Deadline { .. }) {
+        priority.class = Class::Normal;
+    }
+    child.set_priority(priority);
+    child.set_signals(process.get_signals().inherit());
+    child.set_limits(limits);
+    child.set_fs_base(FsBase::read().as_u64());
+    table.insert(child);
+    drop(table);
+    place(pid);
+    Ok(pid)
+}
+
+pub fn clone(
+    context: u64,
+    stack: u64,
+    tls: Option<u64>,
+    clear_child_tid: u64,
+) -> Result<u64, Errno> {
+    let parent = process::get_current();
+    let mut registers = unsafe { (*(context as *const Context)).clone() };
+    registers.rax = 0;
+    if stack != 0 {
+        registers.rsp = stack;
+    }
+    let mut table = PROCESSES.lock();
+    let limits = table
+        .get(parent)
+        .map(|x| x.get_limits().clone())
+        .ok_or(Errno::NoSuchProcess)?;
+    if !limits.get(Resource::Processes).allows(table.count() + 1) {
+        return Err(Errno::Again);
+    }
+    let tid = table.allocate().ok_or(Errno::Again)?;
+    let process = table.get(parent).ok_or(Errno::NoSuchProcess)?;
+    let mut thread = Process::thread(tid, process.get_name(), registers);
+    thread.set_parent(process.get_parent());
+    thread.set_thread_group(process.get_thread_group());
+    thread.set_affinity(process.get_affinity());
+    let mut priority = process.get_priority();
+    if matches!(priority.class, Class::Deadline { .. }) {
+        priority.class = Class::Normal;
+    }
+    thread.set_priority(priority);
+    thread.set_signals(process.get_signals().share());
+    thread.set_limits(limits);
+    thread.set_fs_base(tls.unwrap_or_else(|| FsBase::read().as_u64()));
+    thread.set_clear_child_tid(clear_child_tid);
+    table.insert(thread);
+    Ok(tid)
+}
+
+pub fn alarm(pid: u64) {
+    if let Some(process) = PROCESSES.lock().get(pid) {
+        debug!(
+            "Alarm expired for process #{} ({}).",
+            process.get_id(),
+            process.get_name()
+        );
+    }
+    signal::send(pid, SIGALRM).ok();
+}
+
+pub fn idle() -> ! {
+    loop {
+        deferred::notify();
+        release();
+        balance();
+        thread::yield_now();
+        interrupts::enable_and_hlt();
+    }
+}
+
+pub fn get_load_average() -> [u64; 3] {
+    *LOAD_AVERAGE.lock()
+}
+
+fn calculate_load(_: u64) {
+    let load: u64 = cpu::get_cpus()
+        .iter()
+        .map(|x| x.get_scheduler().lock().get_load())
+        .sum();
+    let mut average = LOAD_AVERAGE.lock();
+    for (value, factor) in average.iter_mut().zip(LOAD_FACTORS) {
+        *value = (*value * factor + (load << LOAD_SHIFT) * (LOAD_ONE - factor)) >> LOAD_SHIFT;
+    }
+}
+
+pub fn initialize_cpu() {
+    let cpu = cpu::current();
+    let mut table = PROCESSES.lock();
+    let pid = table
+        .allocate()
+        .expect("Failed to allocate an idle process ID.");
+    let mut process = Process::new(pid, &format!("idle/{}", cpu.get_id()), State::Running);
+    process.set_affinity(1 << cpu.get_id());
+    table.insert(process);
+    drop(table);
+    cpu.get_scheduler().lock().set_idle(pid);
+    cpu.set_current(pid);
+}
+
+pub fn initialize() {
+    let mut process = Process::new(KERNEL_PID, "kernel", State::Running);
+    process.set_affinity(1 << cpu::current().get_id());
+    PROCESSES.lock().insert(process);
+    cpu::current().set_current(KERNEL_PID);
+    let interval = Duration::from_secs(LOAD_INTERVAL);
+    timer::schedule(
+        interval,
+        Some(interval),
+        Action::Callback(calculate_load, 0),
+    );
 }
@@ -14,20 +14,67 @@
 // You should have received a copy of the GNU General Public License
 // along with this program. If not, see <https://www.gnu.org/licenses/>.
 
+use crate::process::KERNEL_PID;
+use crate::signal::{self, SIGINT};
+use crate::sync::IrqMutex;
+use crate::wait;
 use alloc::string::ToString;
 use core::fmt::{Arguments, Result, Write};
-use spin::{Lazy, Mutex};
+use core::sync::atomic::{AtomicU64, Ordering};
+use spin::Lazy;
 use x86_64::instructions::interrupts::without_interrupts;
 use x86_64::instructions::port::Port;
 
-pub static SERIAL: Lazy<Mutex<Serial>> = Lazy::new(|| {
+const INTERRUPT: u8 = 0x03;
+const CAPACITY: usize = 256;
+
+pub static SERIAL: Lazy<IrqMutex<Serial>> = Lazy::new(|| {
     let serial = Serial::new(Ports::COM1);
     serial.initialize();
-    Mutex::new(serial)
+    IrqMutex::new("SERIAL", serial)
 });
 
+static INPUT: wait::Queue = wait::Queue::new("SERIAL_INPUT");
+static FOREGROUND: AtomicU64 = AtomicU64::new(KERNEL_PID);
+
 pub struct Serial {
     address: u16,
+    input: Input,
+}
+
+struct Input {
+    items: [u8; CAPACITY],
+    head: usize,
+    length: usize,
+}
+
+impl Input {
+    const fn new() -> Input {
+        Input {
+            items: [0; CAPACITY],
+            head: 0,
+            length: 0,
+        }
+    }
+
+    fn push(&mut self, character: u8) {
+        if self.length == CAPACITY {
+            return;
+        }
+        let tail = (self.head + self.length) % CAPACITY;
+        self.items[tail] = character;
+        self.length += 1;
+    }
+
+    fn pop(&mut self) -> Option<u8> {
+        if self.length == 0 {
+            return None;
+        }
+        let character = self.items[self.head];
+        self.head = (self.head + 1) % CAPACITY;
+        self.length -= 1;
+        Some(character)
+    }
 }
 
 pub enum Ports {
@@ -63,6 +110,7 @@ impl Serial {
     pub fn new(port: Ports) -> Serial {
         Serial {
             address: port as u16,
+            input: Input::new(),
         }
     }
 
@@ -102,8 +150,38 @@ impl Serial {
         self.inb(5) & 0x1
     }
 
-    pub fn read(&self) -> u8 {
-        while self.received() == 0 {}
-        self.inb(0)
+    pub fn receive(&mut self) -> bool {
+        let mut interrupted = false;
+        while self.received() != 0 {
+            match self.inb(0) {
+                INTERRUPT => interrupted = true,
+                character => self.input.push(character),
+            }
+        }
+        interrupted
+    }
+
+    pub fn read(&mut self) -> Option<u8> {
+        self.input.pop()
+    }
+}
+
+pub fn interpret(interrupted: u8) {
+    if interrupted != 0 {
+        signal::send(FOREGROUND.load(Ordering::Relaxed), SIGINT).ok();
     }
+    INPUT.wake_all();
+}
+
+pub fn set_foreground(pid: u64) {
+    FOREGROUND.store(pid, Ordering::Relaxed);
+}
+
+pub fn read() -> u8 {
+    let mut character = None;
+    INPUT.wait_until(|| {
+        character = SERIAL.lock().read();
+        character.is_some()
+    });
+    character.unwrap()
 }
@@ -14,39 +14,91 @@
 // You should have received a copy of the GNU General Public License
 // along with this program. If not, see <https://www.gnu.org/licenses/>.
 
-use crate::ansi::{BLUE, BOLD, DEFAULT, GREEN, NORMAL, RED};
+use crate::ansi::{BLUE, BOLD, CLEAR, DEFAULT, GREEN, HOME, NORMAL, RED};
 use crate::elf::Elf;
 use crate::initrd::INITRD;
-use crate::logger::LOGGER;
-use crate::serial::Serial;
+use crate::process::{self, State, PROCESSES};
+use crate::rlimit::{Resource, INFINITY};
+use crate::rtc::DateTime;
+use crate::scheduler::LOAD_SHIFT;
 use crate::serial::SERIAL;
-use crate::syscall;
+use crate::signal::{SIGINT, SIGTERM};
+use crate::syscall::{
+    ClockId, SchedParam, Timespec, PRIO_PROCESS, SCHED_FIFO, SCHED_OTHER, SCHED_RR,
+};
 use crate::timer::TIMER;
-use crate::vga::{Color, VGA};
+use crate::vga::VGA;
+use crate::{cpu, keyboard, logger, scheduler, serial, signal, syscall, thread, userspace};
 use alloc::format;
 use alloc::string::{String, ToString};
 use alloc::vec::Vec;
 use core::fmt::{Result, Write};
 use core::str;
-use spin::{Lazy, Mutex, MutexGuard};
-
-pub static SERIAL_CONSOLE: Lazy<Mutex<Shell>> = Lazy::new(|| {
-    let shell = Shell::new();
-    Mutex::new(shell)
-});
-
-pub static VGA_CONSOLE: Lazy<Mutex<Shell>> = Lazy::new(|| {
-    let mut vga = VGA.lock();
-    let x = vga.get_font_width();
-    let y = vga.get_font_height() + x;
-    vga.clear();
-    vga.set_cursor(x, y, Color::White as u32, Color::Black as u32);
-    let shell = Shell::new();
-    Mutex::new(shell)
-});
+
+const REFRESH_COUNT: u32 = 5;
+const ULIMITS: [(char, Resource, &str, u64); 5] = [
+    ('t', Resource::Cpu, "seconds, ", 1),
+    ('u', Resource::Processes, "", 1),
+    ('n', Resource::Files, "", 1),
+    ('s', Resource::Stack, "kbytes, ", 1024),
+    ('v', Resource::AddressSpace, "kbytes, ", 1024),
+];
+
+struct SerialConsole;
+
+impl Write for SerialConsole {
+    fn write_str(&mut self, s: &str) -> Result {
+        SERIAL.lock().write_str(s)
+    }
+}
+
+struct VgaConsole;
+
+impl Write for VgaConsole {
+    fn write_str(&mut self, s: &str) -> Result {
+        VGA.lock().write_str(s)
+    }
+}
+
+struct Row {
+    pid: u64,
+    parent: u64,
+    cpu: u32,
+    state: State,
+    name: String,
+    start_time: u64,
+    user_time: u64,
+    system_time: u64,
+    switches: u64,
+}
+
+impl Row {
+    fn snapshot() -> Vec<Row> {
+        PROCESSES
+            .lock()
+            .iter()
+            .map(|x| Row {
+                pid: x.get_id(),
+                parent: x.get_parent(),
+                cpu: x.get_cpu(),
+                state: *x.get_state(),
+                name: x.get_name().to_string(),
+                start_time: x.get_start_time(),
+                user_time: x.get_user_time(),
+                system_time: x.get_system_time(),
+                switches: x.get_switches(),
+            })
+            .collect()
+    }
+
+    fn get_cpu_time(&self) -> u64 {
+        self.user_time + self.system_time
+    }
+}
 
 pub struct Shell {
     buffer: Vec<char>,
+    foreground: fn(u64),
     group: String,
     group_id: u32,
     prompt: String,
@@ -56,7 +108,7 @@ pub struct Shell {
 }
 
 impl Shell {
-    pub fn new() -> Shell {
+    pub fn new(foreground: fn(u64)) -> Shell {
         let hostname = str::from_utf8(INITRD.get_data("initrd/etc/hostname"))
             .unwrap()
             .trim_end();
@@ -71,8 +123,10 @@ impl Shell {
             .unwrap()
             .split_terminator(':');
         let group = groups.nth(0).unwrap();
+        foreground(process::get_current());
         Shell {
             buffer: Vec::new(),
+            foreground,
             group: group.to_string(),
             group_id,
             prompt: format!("{BOLD}{DEFAULT}[{GREEN}{username}@{hostname} {BLUE}~{DEFAULT}]# "),
@@ -82,85 +136,19 @@ impl Shell {
         }
     }
 
-    pub fn display<T: Write>(&self, writer: &mut MutexGuard<T>) -> Result {
+    pub fn display<W: Write>(&self, writer: &mut W) -> Result {
         let motd = str::from_utf8(INITRD.get_data("initrd/etc/motd")).unwrap();
         writeln!(writer, "{motd}")?;
         write!(writer, "{}", self.prompt)?;
         Ok(())
     }
 
-    pub fn interpret(&mut self, writer: &mut MutexGuard<Serial>) -> Result {
-        let character = writer.read() as char;
+    pub fn interpret<W: Write>(&mut self, character: char, writer: &mut W) -> Result {
         match character {
             '\r' => {
                 let line: String = self.buffer.iter().collect();
-                let input = line.split_once(char::is_whitespace);
-                let command = match input {
-                    Some(pair) => pair.0,
-                    None => line.as_str(),
-                };
                 writeln!(writer, "{NORMAL}")?;
-                match command {
-                    "echo" => {
-                        let argument = match input {
-                            Some(pair) => pair.1,
-                            None => "",
-                        };
-                        writeln!(writer, "{argument}")?;
-                    }
-                    "fork" => {
-                        let pid = syscall::fork();
-                        writeln!(writer, "Created child process with ID #{pid}.")?;
-                    }
-                    "help" => {
-                        writeln!(writer, "Available commands:")?;
-                        writeln!(writer, "\techo     -- Display a line of text.")?;
-                        writeln!(writer, "\tfork     -- Create child process.")?;
-                        writeln!(writer, "\thelp     -- Print a list of commands.")?;
-                        writeln!(writer, "\tid       -- Print user and group ID.")?;
-                        writeln!(writer, "\tlogs     -- Retrieve the system logs.")?;
-                        writeln!(writer, "\tpwd      -- Print current working directory.")?;
-                        writeln!(writer, "\treadelf  -- Read ELF executable file.")?;
-                        writeln!(writer, "\treboot   -- Reboot the operating system.")?;
-                        writeln!(writer, "\tshutdown -- Shutdown the operating system.")?;
-                        writeln!(writer, "\ttime     -- Display the elapsed time.")?;
-                    }
-                    "id" => {
-                        writeln!(
-                            writer,
-                            "uid={}({}) gid={}({})",
-                            self.user_id, self.username, self.group_id, self.group
-                        )?;
-                    }
-                    "logs" => {
-                        for log in LOGGER.lock().get_logs() {
-                            writeln!(writer, "{log}")?;
-                        }
-                    }
-                    "pwd" => {
-                        writeln!(writer, "{}", self.working_directory)?;
-                    }
-                    "readelf" => {
-                        let argument = match input {
-                            Some(pair) => pair.1,
-                            None => "",
-                        };
-                        let executable = Elf::new(argument);
-                        writeln!(writer, "{executable}")?;
-                    }
-                    "reboot" => {
-                        writeln!(writer, "Rebooting the operating system.")?;
-                    }
-                    "shutdown" => {
-                        writeln!(writer, "Shutting down the operating system.")?;
-                    }
-                    "time" => {
-                        writeln!(writer, "{}", TIMER.get_elapsed())?;
-                    }
-                    _ => {
-                        writeln!(writer, "{RED}ERROR: Command not found.")?;
-                    }
-                }
+                self.execute(&line, writer)?;
                 write!(writer, "{}", self.prompt)?;
                 self.buffer.clear();
             }
@@ -177,11 +165,527 @@ impl Shell {
         }
         Ok(())
     }
+
+    fn execute<W: Write>(&mut self, line: &str, writer: &mut W) -> Result {
+        let (command, argument) = line.split_once(char::is_whitespace).unwrap_or((line, ""));
+        signal::clear();
+        match command {
+            "alarm" => {
+                Shell::alarm(argument, writer)?;
+            }
+            "chrt" => {
+                Shell::chrt(argument, writer)?;
+            }
+            "cpus" => {
+                Shell::cpus(writer)?;
+            }
+            "date" => match syscall::clock_gettime(ClockId::Realtime) {
+                Some(time) => {
+                    let now = DateTime::from_unix(time.seconds);
+                    writeln!(writer, "{}", now.to_date_string())?;
+                }
+                None => {
+                    writeln!(writer, "{RED}ERROR: The wall-clock time is unavailable.")?;
+                }
+            },
+            "echo" => {
+                writeln!(writer, "{argument}")?;
+            }
+            "fork" => {
+                Shell::fork(writer)?;
+            }
+            "help" => {
+                Shell::help(writer)?;
+            }
+            "id" => {
+                writeln!(
+                    writer,
+                    "uid={}({}) gid={}({})",
+                    self.user_id, self.username, self.group_id, self.group
+                )?;
+            }
+            "kill" => {
+                Shell::kill(argument, writer)?;
+            }
+            "logs" => {
+                Shell::logs(writer)?;
+            }
+            "ps" => {
+                Shell::ps(writer)?;
+            }
+            "pwd" => {
+                writeln!(writer, "{}", self.working_directory)?;
+            }
+            "readelf" => {
+                let executable = Elf::new(argument);
+                writeln!(writer, "{executable}")?;
+            }
+            "reboot" => {
+                writeln!(writer, "Rebooting the operating system.")?;
+            }
+            "renice" => {
+                Shell::renice(argument, writer)?;
+            }
+            "shutdown" => {
+                writeln!(writer, "Shutting down the operating system.")?;
+            }
+            "run" => {
+                self.run(argument.trim(), writer)?;
+            }
+            "sleep" => {
+                Shell::sleep(argument, writer)?;
+            }
+            "taskset" => {
+                Shell::taskset(argument, writer)?;
+            }
+            "time" => {
+                let elapsed = syscall::clock_gettime(ClockId::Monotonic).unwrap();
+                writeln!(
+                    writer,
+                    "{}.{:03} seconds",
+                    elapsed.seconds,
+                    elapsed.nanoseconds / 1_000_000
+                )?;
+            }
+            "top" => {
+                Shell::top(argument, writer)?;
+            }
+            "ulimit" => {
+                Shell::ulimit(argument, writer)?;
+            }
+            "uptime" => {
+                writeln!(writer, "{}", Shell::uptime())?;
+            }
+            _ => {
+                writeln!(writer, "{RED}ERROR: Command not found.")?;
+            }
+        }
+        Ok(())
+    }
+
+    fn alarm<W: Write>(argument: &str, writer: &mut W) -> Result {
+        match argument.trim().parse() {
+            Ok(seconds) => {
+                let remaining = syscall::alarm(seconds);
+                writeln!(
+                    writer,
+                    "Previous alarm had {remaining} second(s) remaining."
+                )
+            }
+            Err(_) => writeln!(writer, "{RED}ERROR: Invalid number of seconds."),
+        }
+    }
+
+    fn chrt<W: Write>(argument: &str, writer: &mut W) -> Result {
+        let mut arguments = argument.split_whitespace();
+        let policy = match arguments.next() {
+            Some("other") => Some(SCHED_OTHER),
+            Some("fifo") => Some(SCHED_FIFO),
+            Some("rr") => Some(SCHED_RR),
+            _ => None,
+        };
+        let priority = arguments.next().and_then(|x| x.parse().ok());
+        let pid = arguments.next().and_then(|x| x.parse().ok());
+        let (Some(policy), Some(priority), Some(pid)) = (policy, priority, pid) else {
+            return writeln!(
+                writer,
+                "{RED}ERROR: Usage: chrt <other|fifo|rr> <priority> <pid>."
+            );
+        };
+        match syscall::sched_setscheduler(pid, policy, &SchedParam { priority }) {
+            Ok(()) => writeln!(writer, "Changed the scheduling policy of process #{pid}."),
+            Err(errno) => writeln!(
+                writer,
+                "{RED}ERROR: Failed to set scheduling policy ({errno:?})."
+            ),
+        }
+    }
+
+    fn cpus<W: Write>(writer: &mut W) -> Result {
+        writeln!(writer, "CPU  APIC  PID  INTERRUPTS  TICKS  SWITCHES")?;
+        for cpu in cpu::get_cpus() {
+            let statistics = cpu.get_statistics();
+            writeln!(
+                writer,
+                "{:<4} {:<5} {:<4} {:<11} {:<6} {}",
+                cpu.get_id(),
+                cpu.get_apic_id(),
+                cpu.get_current(),
+                statistics.get_interrupts(),
+                statistics.get_ticks(),
+                statistics.get_switches()
+            )?;
+        }
+        Ok(())
+    }
+
+    fn run<W: Write>(&self, path: &str, writer: &mut W) -> Result {
+        if path.is_empty() {
+            return writeln!(writer, "{RED}ERROR: Usage: run <path>.");
+        }
+        let pid = match userspace::spawn(path, None) {
+            Ok(pid) => pid,
+            Err(errno) => return writeln!(writer, "{RED}ERROR: Failed to run {path} ({errno:?})."),
+        };
+        (self.foreground)(pid);
+        let result = process::wait(Some(pid), true);
+        (self.foreground)(process::get_current());
+        match result {
+            Ok(Some((_, status))) if status != 0 => {
+                writeln!(
+                    writer,
+                    "{RED}ERROR: Process #{pid} exited with status {status}."
+                )
+            }
+            Ok(_) => Ok(()),
+            Err(errno) => writeln!(writer, "{RED}ERROR: Failed to wait for #{pid} ({errno:?})."),
+        }
+    }
+
+    fn fork<W: Write>(writer: &mut W) -> Result {
+        let child = thread::spawn("child", process::get_current);
+        writeln!(writer, "Created child process with ID #{}.", child.get_id())?;
+        match child.join() {
+            Some(pid) => writeln!(writer, "Child process #{pid} finished."),
+            None => writeln!(writer, "{RED}ERROR: Child process exited early."),
+        }
+    }
+
+    fn kill<W: Write>(argument: &str, writer: &mut W) -> Result {
+        let mut arguments = argument.split_whitespace().peekable();
+        let signal = match arguments.next_if(|x| x.starts_with('-')) {
+            Some(name) => signal::parse(&name[1..]),
+            None => Some(SIGTERM),
+        };
+        let pid = arguments.next().and_then(|x| x.parse().ok());
+        let (Some(signal), Some(pid)) = (signal, pid) else {
+            return writeln!(writer, "{RED}ERROR: Usage: kill [-<signal>] <pid>.");
+        };
+        match syscall::kill(pid, signal) {
+            Ok(()) => Ok(()),
+            Err(errno) => writeln!(writer, "{RED}ERROR: Failed to send signal ({errno:?})."),
+        }
+    }
+
+    fn logs<W: Write>(writer: &mut W) -> Result {
+        for log in logger::get_archive().iter() {
+            writeln!(writer, "{log}")?;
+        }
+        Ok(())
+    }
+
+    fn ps<W: Write>(writer: &mut W) -> Result {
+        writeln!(
+            writer,
+            "PID    PPID   CPU  STATE     START     TIME      NAME"
+        )?;
+        for row in Row::snapshot() {
+            writeln!(
+                writer,
+                "{:<6} {:<6} {:<4} {:<9} {:<9} {:<9} {}",
+                row.pid,
+                row.parent,
+                row.cpu,
+                row.state,
+                format_duration(row.start_time),
+                format_duration(row.get_cpu_time()),
+                row.name
+            )?;
+        }
+        Ok(())
+    }
+
+    fn top<W: Write>(argument: &str, writer: &mut W) -> Result {
+        let count = argument.trim().parse().unwrap_or(REFRESH_COUNT);
+        let interval = Timespec {
+            seconds: 1,
+            nanoseconds: 0,
+        };
+        let mut previous = Row::snapshot();
+        let mut last = TIMER.get_nanoseconds();
+        for _ in 0..count {
+            syscall::nanosleep(&interval, None).ok();
+            if signal::take(SIGINT) {
+                break;
+            }
+            let now = TIMER.get_nanoseconds();
+            let elapsed = now.saturating_sub(last).max(1);
+            let mut rows: Vec<(Row, u64)> = Row::snapshot()
+                .into_iter()
+                .map(|row| {
+                    let before = previous
+                        .iter()
+                        .find(|x| x.pid == row.pid)
+                        .map_or(0, Row::get_cpu_time);
+                    let usage = row.get_cpu_time().saturating_sub(before) * 1000 / elapsed;
+                    (row, usage)
+                })
+                .collect();
+            rows.sort_by(|a, b| b.1.cmp(&a.1));
+            let running = rows
+                .iter()
+                .filter(|(x, _)| matches!(x.state, State::Running | State::Ready))
+                .count();
+            let sleeping = rows
+                .iter()
+                .filter(|(x, _)| matches!(x.state, State::Blocked | State::Sleeping))
+                .count();
+            let zombie = rows.len() - running - sleeping;
+            write!(writer, "{CLEAR}{HOME}")?;
+            writeln!(writer, "top - {}", Shell::uptime())?;
+            writeln!(
+                writer,
+                "Tasks: {} total, {running} running, {sleeping} sleeping, {zombie} zombie",
+                rows.len()
+            )?;
+            writeln!(
+                writer,
+                "PID    PPID   STATE     %CPU   USER      SYSTEM    SWITCHES  NAME"
+            )?;
+            for (row, usage) in &rows {
+                writeln!(
+                    writer,
+                    "{:<6} {:<6} {:<9} {:<6} {:<9} {:<9} {:<9} {}",
+                    row.pid,
+                    row.parent,
+                    row.state,
+                    format!("{}.{}", usage / 10, usage % 10),
+                    format_duration(row.user_time),
+                    format_duration(row.system_time),
+                    row.switches,
+                    row.name
+                )?;
+            }
+            previous = rows.into_iter().map(|(x, _)| x).collect();
+            last = now;
+        }
+        Ok(())
+    }
+
+    fn uptime() -> String {
+        let seconds = TIMER.get_milliseconds() / 1000;
+        let [one, five, fifteen] = scheduler::get_load_average().map(format_load);
+        format!(
+            "up {}:{:02}:{:02}, load average: {one}, {five}, {fifteen}",
+            seconds / 3600,
+            seconds / 60 % 60,
+            seconds % 60
+        )
+    }
+
+    fn renice<W: Write>(argument: &str, writer: &mut W) -> Result {
+        let mut arguments = argument.split_whitespace();
+        let priority = arguments.next().and_then(|x| x.parse().ok());
+        let pid = arguments.next().and_then(|x| x.parse().ok());
+        let (Some(priority), Some(pid)) = (priority, pid) else {
+            return writeln!(writer, "{RED}ERROR: Usage: renice <priority> <pid>.");
+        };
+        let old = match syscall::getpriority(PRIO_PROCESS, pid) {
+            Ok(old) => old,
+            Err(errno) => {
+                return writeln!(writer, "{RED}ERROR: Failed to get priority ({errno:?}).");
+            }
+        };
+        if let Err(errno) = syscall::setpriority(PRIO_PROCESS, pid, priority) {
+            return writeln!(writer, "{RED}ERROR: Failed to set priority ({errno:?}).");
+        }
+        let new = syscall::getpriority(PRIO_PROCESS, pid).unwrap_or(priority);
+        writeln!(
+            writer,
+            "{pid} (process ID) old priority {old}, new priority {new}"
+        )
+    }
+
+    fn sleep<W: Write>(argument: &str, writer: &mut W) -> Result {
+        let Ok(seconds) = argument.trim().parse() else {
+            return writeln!(writer, "{RED}ERROR: Invalid number of seconds.");
+        };
+        let request = Timespec {
+            seconds,
+            nanoseconds: 0,
+        };
+        syscall::nanosleep(&request, None).ok();
+        Ok(())
+    }
+
+    fn taskset<W: Write>(argument: &str, writer: &mut W) -> Result {
+        let mut arguments = argument.split_whitespace();
+        let mask = arguments
+            .next()
+            .and_then(|x| u64::from_str_radix(x.trim_start_matches("0x"), 16).ok());
+        let pid = arguments.next().and_then(|x| x.parse().ok());
+        let (Some(mask), Some(pid)) = (mask, pid) else {
+            return writeln!(writer, "{RED}ERROR: Usage: taskset <mask> <pid>.");
+        };
+        let old = match syscall::sched_getaffinity(pid) {
+            Ok(old) => old,
+            Err(errno) => {
+                return writeln!(writer, "{RED}ERROR: Failed to get affinity ({errno:?}).");
+            }
+        };
+        if let Err(errno) = syscall::sched_setaffinity(pid, mask) {
+            return writeln!(writer, "{RED}ERROR: Failed to set affinity ({errno:?}).");
+        }
+        writeln!(
+            writer,
+            "pid {pid}'s current affinity mask: {old:x}\npid {pid}'s new affinity mask: {mask:x}"
+        )
+    }
+
+    fn ulimit<W: Write>(argument: &str, writer: &mut W) -> Result {
+        let mut hard = false;
+        let mut option = 'v';
+        let mut value = None;
+        for argument in argument.split_whitespace() {
+            match argument.strip_prefix('-') {
+                Some("H") => hard = true,
+                Some("S") => hard = false,
+                Some(flag) if flag.len() == 1 => option = flag.chars().next().unwrap_or(option),
+                _ => value = Some(argument),
+            }
+        }
+        if option == 'a' && value.is_some() {
+            option = '?';
+        }
+        let resources = ULIMITS.iter().filter(|x| option == 'a' || x.0 == option);
+        let mut found = false;
+        for (flag, resource, unit, scale) in resources {
+            found = true;
+            let limit = match syscall::getrlimit(*resource as u32) {
+                Ok(limit) => limit,
+                Err(errno) => {
+                    return writeln!(writer, "{RED}ERROR: Failed to get limit ({errno:?}).");
+                }
+            };
+            let Some(value) = value else {
+                let current = if hard { limit.maximum } else { limit.current };
+                let current = if current == INFINITY {
+                    "unlimited".to_string()
+                } else {
+                    (current / scale).to_string()
+                };
+                let label = format!("{} ({unit}-{flag})", resource.get_name());
+                writeln!(writer, "{label:<32} {current}")?;
+                continue;
+            };
+            let parsed = match value {
+                "unlimited" => Some(INFINITY),
+                _ => value
+                    .parse::<u64>()
+                    .ok()
+                    .and_then(|x| x.checked_mul(*scale)),
+            };
+            let Some(parsed) = parsed else {
+                return writeln!(writer, "{RED}ERROR: Invalid limit '{value}'.");
+            };
+            let mut new = limit;
+            if hard {
+                new.maximum = parsed;
+                new.current = new.current.min(parsed);
+            } else {
+                new.current = parsed;
+            }
+            if let Err(errno) = syscall::setrlimit(*resource as u32, &new) {
+                return writeln!(writer, "{RED}ERROR: Failed to set limit ({errno:?}).");
+            }
+        }
+        if !found {
+            return writeln!(
+                writer,
+                "{RED}ERROR: Usage: ulimit [-H|-S] [-a|-n|-s|-t|-u|-v] [limit]."
+            );
+        }
+        Ok(())
+    }
+
+    fn help<W: Write>(writer: &mut W) -> Result {
+        writeln!(writer, "Available commands:")?;
+        writeln!(
+            writer,
+            "\talarm    -- Schedule an alarm in a number of seconds."
+        )?;
+        writeln!(
+            writer,
+            "\tchrt     -- Set the scheduling policy of a process."
+        )?;
+        writeln!(writer, "\tcpus     -- Display per-CPU statistics.")?;
+        writeln!(writer, "\tdate     -- Display the current date and time.")?;
+        writeln!(writer, "\techo     -- Display a line of text.")?;
+        writeln!(writer, "\tfork     -- Create child process.")?;
+        writeln!(writer, "\thelp     -- Print a list of commands.")?;
+        writeln!(writer, "\tid       -- Print user and group ID.")?;
+        writeln!(writer, "\tkill     -- Send a signal to a process.")?;
+        writeln!(writer, "\tlogs     -- Retrieve the system logs.")?;
+        writeln!(
+            writer,
+            "\tps       -- Report a snapshot of the current processes."
+        )?;
+        writeln!(writer, "\tpwd      -- Print current working directory.")?;
+        writeln!(writer, "\treadelf  -- Read ELF executable file.")?;
+        writeln!(writer, "\treboot   -- Reboot the operating system.")?;
+        writeln!(
+            writer,
+            "\trenice   -- Alter the priority of a running process."
+        )?;
+        writeln!(writer, "\trun      -- Run a program from the initrd.")?;
+        writeln!(writer, "\tshutdown -- Shutdown the operating system.")?;
+        writeln!(writer, "\tsleep    -- Pause for a number of seconds.")?;
+        writeln!(writer, "\ttaskset  -- Set the CPU affinity of a process.")?;
+        writeln!(writer, "\ttime     -- Display the elapsed time.")?;
+        writeln!(
+            writer,
+            "\ttop      -- Display processes sorted by CPU usage."
+        )?;
+        writeln!(writer, "\tulimit   -- Get and set process resource limits.")?;
+        writeln!(writer, "\tuptime   -- Display how long the system has run.")?;
+        Ok(())
+    }
 }
 
-pub fn initialize() {
-    SERIAL_CONSOLE
-        .lock()
-        .display(&mut SERIAL.lock())
+fn format_duration(nanoseconds: u64) -> String {
+    let centiseconds = nanoseconds / 10_000_000;
+    format!(
+        "{}:{:02}.{:02}",
+        centiseconds / 6000,
+        centiseconds / 100 % 60,
+        centiseconds % 100
+    )
+}
+
+fn format_load(load: u64) -> String {
+    let fraction = ((load & ((1 << LOAD_SHIFT) - 1)) * 100) >> LOAD_SHIFT;
+    format!("{}.{fraction:02}", load >> LOAD_SHIFT)
+}
+
+fn serial_console() {
+    let mut shell = Shell::new(serial::set_foreground);
+    shell
+        .display(&mut SerialConsole)
         .expect("Failed to display serial console.");
+    loop {
+        let character = serial::read();
+        shell
+            .interpret(character as char, &mut SerialConsole)
+            .expect("Failed to interpret serial console input.");
+    }
+}
+
+fn vga_console() {
+    while keyboard::read() != b'\r' {}
+    let mut shell = Shell::new(keyboard::set_foreground);
+    VGA.lock().clear();
+    shell
+        .display(&mut VgaConsole)
+        .expect("Failed to display VGA console.");
+    loop {
+        let character = keyboard::read();
+        shell
+            .interpret(character as char, &mut VgaConsole)
+            .expect("Failed to interpret VGA console input.");
+    }
+}
+
+pub fn initialize() {
+    thread::spawn("serial-shell", serial_console);
+    thread::spawn("vga-shell", vga_console);
 }
@@ -0,0 +1,413 @@
+// NeurOS - Hobbyist operating system written in Rust.
+// Copyright (C) 2024 Theomund
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program. If not, see <https://www.gnu.org/licenses/>.
+
+use crate::logger::{Level, LOGGER};
+use crate::process::{self, Context, State, PROCESSES};
+use crate::sync::IrqMutex;
+use crate::syscall::Errno;
+use crate::{cpu, debug, gdt, memory, scheduler};
+use alloc::format;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use core::mem::{self, offset_of};
+use x86_64::structures::paging::PageTableFlags;
+
+pub const SIGINT: u32 = 2;
+pub const SIGQUIT: u32 = 3;
+pub const SIGILL: u32 = 4;
+pub const SIGTRAP: u32 = 5;
+pub const SIGABRT: u32 = 6;
+pub const SIGBUS: u32 = 7;
+pub const SIGFPE: u32 = 8;
+pub const SIGKILL: u32 = 9;
+pub const SIGSEGV: u32 = 11;
+pub const SIGALRM: u32 = 14;
+pub const SIGTERM: u32 = 15;
+pub const SIGCHLD: u32 = 17;
+pub const SIGCONT: u32 = 18;
+pub const SIGSTOP: u32 = 19;
+pub const SIGTSTP: u32 = 20;
+pub const SIGTTIN: u32 = 21;
+pub const SIGTTOU: u32 = 22;
+pub const SIGURG: u32 = 23;
+pub const SIGXCPU: u32 = 24;
+pub const SIGXFSZ: u32 = 25;
+pub const SIGWINCH: u32 = 28;
+pub const SIGSYS: u32 = 31;
+pub const NSIG: u32 = 32;
+
+pub const SA_RESTORER: u64 = 0x0400_0000;
+pub const SA_NODEFER: u64 = 0x4000_0000;
+pub const SA_RESETHAND: u64 = 0x8000_0000;
+
+const NAMES: [&str; NSIG as usize] = [
+    "0", "HUP", "INT", "QUIT", "ILL", "TRAP", "ABRT", "BUS", "FPE", "KILL", "USR1", "SEGV", "USR2",
+    "PIPE", "ALRM", "TERM", "STKFLT", "CHLD", "CONT", "STOP", "TSTP", "TTIN", "TTOU", "URG",
+    "XCPU", "XFSZ", "VTALRM", "PROF", "WINCH", "IO", "PWR", "SYS",
+];
+const UNBLOCKABLE: u64 = bit(SIGKILL) | bit(SIGSTOP);
+const STOPPING: u64 = bit(SIGSTOP) | bit(SIGTSTP) | bit(SIGTTIN) | bit(SIGTTOU);
+const RED_ZONE: u64 = 128;
+const USER_FLAGS: u64 = 0x0004_0DD5;
+
+#[derive(Clone, Copy)]
+pub enum Disposition {
+    Terminate,
+    Core,
+    Stop,
+    Continue,
+    Ignore,
+}
+
+#[derive(Clone, Copy)]
+pub struct Handler {
+    pub address: u64,
+    pub restorer: u64,
+    pub mask: u64,
+    pub flags: u64,
+}
+
+#[derive(Clone, Copy, Default)]
+pub enum Action {
+    #[default]
+    Default,
+    Ignore,
+    Handle(Handler),
+}
+
+#[repr(C)]
+struct Frame {
+    restorer: u64,
+    signal: u64,
+    mask: u64,
+    context: Context,
+}
+
+pub struct Signals {
+    pending: u64,
+    blocked: u64,
+    actions: Arc<IrqMutex<[Action; NSIG as usize]>>,
+}
+
+impl Default for Signals {
+    fn default() -> Signals {
+        Signals {
+            pending: 0,
+            blocked: 0,
+            actions: Arc::new(IrqMutex::new(
+                "SIGNAL_ACTIONS",
+                [Action::Default; NSIG as usize],
+            )),
+        }
+    }
+}
+
+impl Signals {
+    pub fn inherit(&self) -> Signals {
+        Signals {
+            pending: 0,
+            blocked: self.blocked,
+            actions: Arc::new(IrqMutex::new("SIGNAL_ACTIONS", *self.actions.lock())),
+        }
+    }
+
+    pub fn share(&self) -> Signals {
+        Signals {
+            pending: 0,
+            blocked: self.blocked,
+            actions: self.actions.clone(),
+        }
+    }
+
+    pub fn get_action(&self, signal: u32) -> Action {
+        self.actions.lock()[signal as usize]
+    }
+
+    pub fn get_blocked(&self) -> u64 {
+        self.blocked
+    }
+
+    pub fn get_pending(&self) -> u64 {
+        self.pending
+    }
+
+    pub fn set_action(&mut self, signal: u32, action: Action) {
+        self.actions.lock()[signal as usize] = action;
+        if self.is_ignored(signal) {
+            self.pending &= !bit(signal);
+        }
+    }
+
+    pub fn set_blocked(&mut self, mask: u64) {
+        self.blocked = mask & !UNBLOCKABLE;
+    }
+
+    pub fn is_pending(&self) -> bool {
+        self.pending & !self.blocked != 0
+    }
+
+    fn is_ignored(&self, signal: u32) -> bool {
+        match self.get_action(signal) {
+            Action::Default => matches!(get_disposition(signal), Disposition::Ignore),
+            Action::Ignore => true,
+            Action::Handle(_) => false,
+        }
+    }
+
+    pub fn raise(&mut self, signal: u32) {
+        match get_disposition(signal) {
+            Disposition::Stop => self.pending &= !bit(SIGCONT),
+            Disposition::Continue => self.pending &= !STOPPING,
+            _ => {}
+        }
+        if !self.is_ignored(signal) {
+            self.pending |= bit(signal);
+        }
+    }
+
+    pub fn take(&mut self, signal: u32) -> bool {
+        let pending = self.pending & bit(signal) != 0;
+        self.pending &= !bit(signal);
+        pending
+    }
+
+    pub fn clear(&mut self) {
+        self.pending = 0;
+    }
+
+    fn dequeue(&mut self) -> Option<(u32, Action)> {
+        let deliverable = self.pending & !self.blocked;
+        if deliverable == 0 {
+            return None;
+        }
+        let signal = deliverable.trailing_zeros() + 1;
+        self.pending &= !bit(signal);
+        Some((signal, self.get_action(signal)))
+    }
+
+    fn force(&mut self, signal: u32) {
+        if self.blocked & bit(signal) != 0 || self.is_ignored(signal) {
+            self.set_action(signal, Action::Default);
+        }
+        self.blocked &= !bit(signal);
+        self.raise(signal);
+    }
+}
+
+const fn bit(signal: u32) -> u64 {
+    1 << (signal - 1)
+}
+
+pub fn get_name(signal: u32) -> Option<&'static str> {
+    NAMES.get(signal as usize).copied()
+}
+
+pub fn parse(name: &str) -> Option<u32> {
+    let name = name.trim_start_matches("SIG");
+    match name.parse() {
+        Ok(signal) if signal < NSIG => Some(signal),
+        Ok(_) => None,
+        Err(_) => (1..NSIG).find(|x| NAMES[*x as usize].eq_ignore_ascii_case(name)),
+    }
+}
+
+pub fn get_disposition(signal: u32) -> Disposition {
+    match signal {
+        SIGQUIT | SIGILL | SIGTRAP | SIGABRT | SIGBUS | SIGFPE | SIGSEGV | SIGXCPU | SIGXFSZ
+        | SIGSYS => Disposition::Core,
+        SIGSTOP | SIGTSTP | SIGTTIN | SIGTTOU => Disposition::Stop,
+        SIGCONT => Disposition::Continue,
+        SIGCHLD | SIGURG | SIGWINCH => Disposition::Ignore,
+        _ => Disposition::Terminate,
+    }
+}
+
+pub fn is_valid(signal: u32) -> bool {
+    signal < NSIG
+}
+
+pub fn is_catchable(signal: u32) -> bool {
+    (1..NSIG).contains(&signal) && UNBLOCKABLE & bit(signal) == 0
+}
+
+pub fn send(pid: u64, signal: u32) -> Result<(), Errno> {
+    if !is_valid(signal) {
+        return Err(Errno::Invalid);
+    }
+    let mut table = PROCESSES.lock();
+    let process = table.get_mut(pid).ok_or(Errno::NoSuchProcess)?;
+    if signal == 0 || process.is_zombie() {
+        return Ok(());
+    }
+    let signals = process.get_signals_mut();
+    signals.raise(signal);
+    let pending = signals.is_pending();
+    let stopped = matches!(process.get_state(), State::Stopped);
+    drop(table);
+    debug!(
+        "Sent signal SIG{} to process #{pid}.",
+        get_name(signal).unwrap_or_default()
+    );
+    if stopped && (signal == SIGCONT || signal == SIGKILL) {
+        scheduler::resume(pid);
+    } else if pending {
+        scheduler::wake(pid);
+    }
+    Ok(())
+}
+
+pub fn force(pid: u64, signal: u32) {
+    if let Some(process) = PROCESSES.lock().get_mut(pid) {
+        process.get_signals_mut().force(signal);
+    }
+}
+
+pub fn is_pending() -> bool {
+    PROCESSES
+        .lock()
+        .get(process::get_current())
+        .is_some_and(|x| x.get_signals().is_pending())
+}
+
+pub fn take(signal: u32) -> bool {
+    PROCESSES
+        .lock()
+        .get_mut(process::get_current())
+        .is_some_and(|x| x.get_signals_mut().take(signal))
+}
+
+pub fn clear() {
+    if let Some(process) = PROCESSES.lock().get_mut(process::get_current()) {
+        process.get_signals_mut().clear();
+    }
+}
+
+fn setup(context: &mut Context, signal: u32, handler: &Handler, mask: u64) -> bool {
+    let size = mem::size_of::<Frame>() as u64;
+    let Some(address) = context
+        .rsp
+        .checked_sub(RED_ZONE + size)
+        .map(|x| (x & !0xF).wrapping_sub(8))
+    else {
+        return false;
+    };
+    if handler.restorer == 0 || !memory::is_user_accessible(address, size, PageTableFlags::WRITABLE)
+    {
+        return false;
+    }
+    let frame = Frame {
+        restorer: handler.restorer,
+        signal: u64::from(signal),
+        mask,
+        context: context.clone(),
+    };
+    unsafe {
+        (address as *mut Frame).write(frame);
+    }
+    context.rip = handler.address;
+    context.rsp = address;
+    context.rdi = u64::from(signal);
+    context.rsi = 0;
+    context.rdx = address + offset_of!(Frame, context) as u64;
+    true
+}
+
+pub fn deliver(mut context: u64) -> u64 {
+    while process::is_user(context) {
+        let pid = process::get_current();
+        let mut table = PROCESSES.lock();
+        let Some(process) = table.get_mut(pid) else {
+            break;
+        };
+        let signals = process.get_signals_mut();
+        let Some((signal, action)) = signals.dequeue() else {
+            break;
+        };
+        let name = get_name(signal).unwrap_or_default();
+        let disposition = match action {
+            Action::Handle(handler) => {
+                let mask = signals.get_blocked();
+                let registers = unsafe { &mut *(context as *mut Context) };
+                if setup(registers, signal, &handler, mask) {
+                    let mut blocked = mask | handler.mask;
+                    if handler.flags & SA_NODEFER == 0 {
+                        blocked |= bit(signal);
+                    }
+                    signals.set_blocked(blocked);
+                    if handler.flags & SA_RESETHAND != 0 {
+                        signals.set_action(signal, Action::Default);
+                    }
+                    break;
+                }
+                signals.force(SIGSEGV);
+                continue;
+            }
+            Action::Ignore => continue,
+            Action::Default => get_disposition(signal),
+        };
+        let threads = match disposition {
+            Disposition::Terminate | Disposition::Core => {
+                let core = matches!(disposition, Disposition::Core);
+                debug!(
+                    "Process #{pid} ({}) was killed by SIG{name}{}.",
+                    process.get_name(),
+                    if core { " (core dumped)" } else { "" }
+                );
+                let status = 128 + i32::try_from(signal).unwrap();
+                let threads = table.terminate_group(pid, status);
+                table.exit(pid, status);
+                threads
+            }
+            Disposition::Stop => {
+                debug!(
+                    "Process #{pid} ({}) was stopped by SIG{name}.",
+                    process.get_name()
+                );
+                process.set_state(State::Stopped);
+                Vec::new()
+            }
+            Disposition::Continue | Disposition::Ignore => continue,
+        };
+        drop(table);
+        for thread in threads {
+            send(thread, SIGKILL).ok();
+        }
+        context = cpu::current().get_scheduler().lock().yield_now(context);
+    }
+    context
+}
+
+pub fn restore(context: u64) -> u64 {
+    let registers = unsafe { &mut *(context as *mut Context) };
+    let address = registers.rsp.wrapping_sub(8);
+    let size = mem::size_of::<Frame>() as u64;
+    if !memory::is_user_accessible(address, size, PageTableFlags::empty()) {
+        force(process::get_current(), SIGSEGV);
+        return deliver(context);
+    }
+    let frame = unsafe { (address as *const Frame).read() };
+    let flags = (registers.rflags & !USER_FLAGS) | (frame.context.rflags & USER_FLAGS);
+    *registers = Context {
+        cs: u64::from(gdt::get_user_code().0),
+        ss: u64::from(gdt::get_user_data().0),
+        rflags: flags,
+        ..frame.context
+    };
+    if let Some(process) = PROCESSES.lock().get_mut(process::get_current()) {
+        process.get_signals_mut().set_blocked(frame.mask);
+    }
+    deliver(context)
+}
@@ -14,16 +14,98 @@
 // You should have received a copy of the GNU General Public License
 // along with this program. If not, see <https://www.gnu.org/licenses/>.
 
-use crate::debug;
+use crate::apic::LAPIC;
 use crate::logger::{Level, LOGGER};
+use crate::{cpu, debug, info, interrupts, scheduler, timer, userspace};
 use alloc::format;
+use alloc::vec;
+use alloc::vec::Vec;
+use core::arch::asm;
+use core::hint;
+use core::sync::atomic::{AtomicUsize, Ordering};
 use limine::request::SmpRequest;
+use limine::smp::Cpu;
+use spin::Once;
+
+const STACK_SIZE: usize = 0x8000;
 
 #[used]
 #[link_section = ".requests"]
 static SMP_REQUEST: SmpRequest = SmpRequest::new();
 
+static ONLINE: AtomicUsize = AtomicUsize::new(1);
+
+static PREPARED: Once<Vec<(&'static cpu::Cpu, u64)>> = Once::new();
+
+unsafe extern "C" fn start(cpu: &Cpu) -> ! {
+    let (processor, top) = PREPARED
+        .get()
+        .and_then(|x| x.iter().find(|(x, _)| x.get_apic_id() == cpu.lapic_id))
+        .copied()
+        .unwrap();
+    asm!(
+        "mov rsp, {top}",
+        "xor rbp, rbp",
+        "call {enter}",
+        top = in(reg) top,
+        enter = sym enter,
+        in("rdi") processor,
+        options(noreturn)
+    );
+}
+
+extern "C" fn enter(cpu: &'static cpu::Cpu) -> ! {
+    cpu::initialize(cpu);
+    interrupts::initialize_cpu();
+    let lapic = LAPIC.get().expect("The local APIC was not initialized.");
+    lapic.enable();
+    scheduler::initialize_cpu();
+    cpu::online(cpu);
+    timer::initialize_cpu();
+    userspace::initialize_cpu();
+    info!(
+        "Started CPU #{} with APIC ID {}.",
+        cpu.get_id(),
+        lapic.get_id()
+    );
+    ONLINE.fetch_add(1, Ordering::Release);
+    scheduler::idle();
+}
+
 pub fn initialize() {
-    let count = SMP_REQUEST.get_response().unwrap().cpus().len();
-    debug!("Detected that the processor has {count} core(s).");
+    let response = SMP_REQUEST.get_response().unwrap();
+    let cpus = response.cpus();
+    debug!("Detected that the processor has {} core(s).", cpus.len());
+    for (index, cpu) in cpus.iter().enumerate() {
+        if cpu.lapic_id == response.bsp_lapic_id() {
+            let bsp = cpu::create(u32::try_from(index).unwrap(), cpu.lapic_id, true);
+            cpu::initialize(bsp);
+            cpu::online(bsp);
+            info!(
+                "Started the bootstrap processor #{index} with APIC ID {}.",
+                cpu.lapic_id
+            );
+        }
+    }
+    PREPARED.call_once(|| {
+        cpus.iter()
+            .enumerate()
+            .filter(|(_, x)| x.lapic_id != response.bsp_lapic_id())
+            .map(|(index, x)| {
+                let processor = cpu::create(u32::try_from(index).unwrap(), x.lapic_id, false);
+                let stack = vec![0u8; STACK_SIZE].leak();
+                let top = (stack.as_ptr() as u64 + STACK_SIZE as u64) & !0xF;
+                (processor, top)
+            })
+            .collect()
+    });
+    for cpu in cpus {
+        if cpu.lapic_id != response.bsp_lapic_id() {
+            cpu.goto_address.write(start);
+        }
+    }
+    while ONLINE.load(Ordering::Acquire) < cpus.len() {
+        hint::spin_loop();
+    }
+    debug!("Brought {} core(s) online.", cpus.len());
 }
@@ -0,0 +1,189 @@
+// NeurOS - Hobbyist operating system written in Rust.
+// Copyright (C) 2024 Theomund
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program. If not, see <https://www.gnu.org/licenses/>.
+
+#[cfg(debug_assertions)]
+use crate::cpu;
+#[cfg(feature = "lockdep")]
+use crate::lockdep::{self, Class};
+use core::mem::ManuallyDrop;
+use core::ops::{Deref, DerefMut};
+#[cfg(feature = "lockdep")]
+use core::panic::Location;
+#[cfg(debug_assertions)]
+use core::sync::atomic::{AtomicU32, Ordering};
+use x86_64::instructions::interrupts;
+
+#[cfg(debug_assertions)]
+const UNOWNED: u32 = u32::MAX;
+
+pub struct IrqMutex<T> {
+    #[cfg_attr(not(any(debug_assertions, feature = "lockdep")), allow(dead_code))]
+    name: &'static str,
+    inner: spin::Mutex<T>,
+    #[cfg(debug_assertions)]
+    owner: AtomicU32,
+    #[cfg(feature = "lockdep")]
+    class: Class,
+}
+
+pub struct IrqMutexGuard<'a, T> {
+    guard: ManuallyDrop<spin::MutexGuard<'a, T>>,
+    #[cfg_attr(not(any(debug_assertions, feature = "lockdep")), allow(dead_code))]
+    lock: &'a IrqMutex<T>,
+    enabled: bool,
+}
+
+impl<T> IrqMutex<T> {
+    pub const fn new(name: &'static str, value: T) -> IrqMutex<T> {
+        IrqMutex {
+            name,
+            inner: spin::Mutex::new(value),
+            #[cfg(debug_assertions)]
+            owner: AtomicU32::new(UNOWNED),
+            #[cfg(feature = "lockdep")]
+            class: Class::new(),
+        }
+    }
+
+    #[track_caller]
+    pub fn lock(&self) -> IrqMutexGuard<'_, T> {
+        let enabled = interrupts::are_enabled();
+        interrupts::disable();
+
+        #[cfg(debug_assertions)]
+        let cpu = cpu::get_current_id();
+        #[cfg(debug_assertions)]
+        assert!(
+            self.owner.load(Ordering::Relaxed) != cpu,
+            "Detected re-entrant acquisition of the {} lock on CPU #{cpu}.",
+            self.name
+        );
+
+        #[cfg(feature = "lockdep")]
+        lockdep::acquire(&self.class, self.name, Location::caller(), false);
+
+        let guard = self.inner.lock();
+
+        #[cfg(debug_assertions)]
+        self.owner.store(cpu, Ordering::Relaxed);
+
+        IrqMutexGuard {
+            guard: ManuallyDrop::new(guard),
+            lock: self,
+            enabled,
+        }
+    }
+
+    pub unsafe fn force_unlock(&self) {
+        #[cfg(debug_assertions)]
+        self.owner.store(UNOWNED, Ordering::Relaxed);
+        self.inner.force_unlock();
+    }
+}
+
+impl<T> Deref for IrqMutexGuard<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.guard
+    }
+}
+
+impl<T> DerefMut for IrqMutexGuard<'_, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        &mut self.guard
+    }
+}
+
+impl<T> Drop for IrqMutexGuard<'_, T> {
+    fn drop(&mut self) {
+        #[cfg(debug_assertions)]
+        self.lock.owner.store(UNOWNED, Ordering::Relaxed);
+        #[cfg(feature = "lockdep")]
+        lockdep::release(&self.lock.class);
+        unsafe {
+            ManuallyDrop::drop(&mut self.guard);
+        }
+        if self.enabled {
+            interrupts::enable();
+        }
+    }
+}
+
+pub struct SpinLock<T> {
+    #[cfg_attr(not(feature = "lockdep"), allow(dead_code))]
+    name: &'static str,
+    inner: spin::Mutex<T>,
+    #[cfg(feature = "lockdep")]
+    class: Class,
+}
+
+pub struct SpinLockGuard<'a, T> {
+    guard: ManuallyDrop<spin::MutexGuard<'a, T>>,
+    #[cfg_attr(not(feature = "lockdep"), allow(dead_code))]
+    lock: &'a SpinLock<T>,
+}
+
+impl<T> SpinLock<T> {
+    pub const fn new(name: &'static str, value: T) -> SpinLock<T> {
+        SpinLock {
+            name,
+            inner: spin::Mutex::new(value),
+            #[cfg(feature = "lockdep")]
+            class: Class::new(),
+        }
+    }
+
+    #[track_caller]
+    pub fn lock(&self) -> SpinLockGuard<'_, T> {
+        #[cfg(feature = "lockdep")]
+        lockdep::acquire(
+            &self.class,
+            self.name,
+            Location::caller(),
+            interrupts::are_enabled(),
+        );
+
+        SpinLockGuard {
+            guard: ManuallyDrop::new(self.inner.lock()),
+            lock: self,
+        }
+    }
+}
+
+impl<T> Deref for SpinLockGuard<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.guard
+    }
+}
+
+impl<T> DerefMut for SpinLockGuard<'_, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        &mut self.guard
+    }
+}
+
+impl<T> Drop for SpinLockGuard<'_, T> {
+    fn drop(&mut self) {
+        #[cfg(feature = "lockdep")]
+        lockdep::release(&self.lock.class);
+        unsafe {
+            ManuallyDrop::drop(&mut self.guard);
+        }
+    }
+}
@@ -14,40 +14,1068 @@
 // You should have received a copy of the GNU General Public License
 // along with this program. If not, see <https://www.gnu.org/licenses/>.
 
-use crate::scheduler::SCHEDULER;
+use crate::memory::PAGE_SIZE;
+use crate::process::{self, Context, State, PROCESSES};
+use crate::realtime::{self, MAXIMUM_PRIORITY};
+use crate::rlimit::{Limit, Resource};
+use crate::scheduler::{self, Class, Priority};
+use crate::serial::SERIAL;
+use crate::signal::{self, Handler, SA_RESTORER};
+use crate::timer;
+use crate::timer::{Action, TIMER};
+use crate::vga::VGA;
+use crate::{cpu, futex, memory, thread};
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::fmt::Write;
+use core::mem;
+use core::ptr;
+use core::slice;
+use core::time::Duration;
+use x86_64::instructions::interrupts;
+use x86_64::registers::model_specific::FsBase;
+use x86_64::structures::paging::{PageTableFlags, PhysFrame};
+use x86_64::VirtAddr;
 
-pub fn close() {
-    todo!("Implement system call.");
+pub const ARCH_SET_FS: u32 = 0x1002;
+pub const ARCH_GET_FS: u32 = 0x1003;
+pub const CLONE_VM: u64 = 0x0000_0100;
+pub const CLONE_FS: u64 = 0x0000_0200;
+pub const CLONE_FILES: u64 = 0x0000_0400;
+pub const CLONE_SIGHAND: u64 = 0x0000_0800;
+pub const CLONE_THREAD: u64 = 0x0001_0000;
+pub const CLONE_SETTLS: u64 = 0x0008_0000;
+pub const CLONE_PARENT_SETTID: u64 = 0x0010_0000;
+pub const CLONE_CHILD_CLEARTID: u64 = 0x0020_0000;
+pub const CLONE_CHILD_SETTID: u64 = 0x0100_0000;
+pub const FUTEX_WAIT: u32 = 0;
+pub const FUTEX_WAKE: u32 = 1;
+pub const FUTEX_PRIVATE_FLAG: u32 = 128;
+pub const ITIMER_REAL: u32 = 0;
+pub const CLOCK_REALTIME: u32 = 0;
+pub const CLOCK_MONOTONIC: u32 = 1;
+pub const PRIO_PROCESS: u32 = 0;
+pub const PROT_READ: u32 = 1;
+pub const PROT_WRITE: u32 = 2;
+pub const PROT_EXEC: u32 = 4;
+pub const SCHED_OTHER: u32 = 0;
+pub const SCHED_FIFO: u32 = 1;
+pub const SCHED_RR: u32 = 2;
+pub const SCHED_DEADLINE: u32 = 6;
+pub const WNOHANG: u32 = 1;
+pub const SIG_DFL: u64 = 0;
+pub const SIG_IGN: u64 = 1;
+pub const SIG_BLOCK: u32 = 0;
+pub const SIG_UNBLOCK: u32 = 1;
+pub const SIG_SETMASK: u32 = 2;
+
+const SYS_WRITE: usize = 1;
+const SYS_MPROTECT: usize = 10;
+const SYS_MUNMAP: usize = 11;
+const SYS_RT_SIGACTION: usize = 13;
+const SYS_RT_SIGPROCMASK: usize = 14;
+const SYS_RT_SIGRETURN: usize = 15;
+const SYS_SCHED_YIELD: usize = 24;
+const SYS_NANOSLEEP: usize = 35;
+const SYS_GETITIMER: usize = 36;
+const SYS_ALARM: usize = 37;
+const SYS_SETITIMER: usize = 38;
+const SYS_GETPID: usize = 39;
+const SYS_CLONE: usize = 56;
+const SYS_FORK: usize = 57;
+const SYS_EXIT: usize = 60;
+const SYS_WAIT4: usize = 61;
+const SYS_KILL: usize = 62;
+const SYS_GETRLIMIT: usize = 97;
+const SYS_RT_SIGPENDING: usize = 127;
+// x86-64 has no nice system call; libc implements it with getpriority and
+// setpriority.
+const SYS_GETPRIORITY: usize = 140;
+const SYS_SETPRIORITY: usize = 141;
+const SYS_SCHED_SETSCHEDULER: usize = 144;
+const SYS_SCHED_GETSCHEDULER: usize = 145;
+const SYS_ARCH_PRCTL: usize = 158;
+const SYS_SETRLIMIT: usize = 160;
+const SYS_GETTID: usize = 186;
+const SYS_FUTEX: usize = 202;
+const SYS_SCHED_SETAFFINITY: usize = 203;
+const SYS_SCHED_GETAFFINITY: usize = 204;
+const SYS_CLOCK_GETTIME: usize = 228;
+const SYS_EXIT_GROUP: usize = 231;
+const SYS_PRLIMIT64: usize = 302;
+const SYSTEM_CALLS: usize = 335;
+const STDOUT: u64 = 1;
+const STDERR: u64 = 2;
+const NICE_OFFSET: i32 = 20;
+const CLONE_THREAD_FLAGS: u64 = CLONE_VM | CLONE_FS | CLONE_SIGHAND | CLONE_THREAD;
+const MINIMUM_NICE: i32 = -20;
+const MAXIMUM_NICE: i32 = 19;
+
+#[derive(Clone, Copy, Debug)]
+#[repr(i64)]
+pub enum Errno {
+    Permission = 1,
+    NoEntry = 2,
+    NoSuchProcess = 3,
+    Interrupted = 4,
+    NoExec = 8,
+    BadDescriptor = 9,
+    NoChild = 10,
+    Again = 11,
+    NoMemory = 12,
+    Fault = 14,
+    Busy = 16,
+    Invalid = 22,
+    NoSystemCall = 38,
+    TimedOut = 110,
+}
+
+#[derive(Clone, Copy)]
+pub enum ClockId {
+    Realtime,
+    Monotonic,
+}
+
+#[derive(Default)]
+#[repr(C)]
+pub struct Timespec {
+    pub seconds: u64,
+    pub nanoseconds: u32,
+}
+
+impl Timespec {
+    fn from_duration(duration: Duration) -> Timespec {
+        Timespec {
+            seconds: duration.as_secs(),
+            nanoseconds: duration.subsec_nanos(),
+        }
+    }
+
+    fn to_duration(&self) -> Duration {
+        Duration::new(self.seconds, self.nanoseconds)
+    }
+}
+
+pub struct SchedParam {
+    pub priority: u32,
+}
+
+pub struct SchedAttr {
+    pub policy: u32,
+    pub nice: i32,
+    pub priority: u32,
+    pub runtime: u64,
+    pub deadline: u64,
+    pub period: u64,
+}
+
+impl SchedAttr {
+    fn from_priority(priority: Priority) -> SchedAttr {
+        let mut attributes = SchedAttr {
+            policy: SCHED_OTHER,
+            nice: i32::from(priority.nice),
+            priority: 0,
+            runtime: 0,
+            deadline: 0,
+            period: 0,
+        };
+        match priority.class {
+            Class::Normal => {}
+            Class::Fifo(priority) => {
+                attributes.policy = SCHED_FIFO;
+                attributes.priority = u32::from(priority);
+            }
+            Class::RoundRobin(priority) => {
+                attributes.policy = SCHED_RR;
+                attributes.priority = u32::from(priority);
+            }
+            Class::Deadline {
+                runtime,
+                deadline,
+                period,
+            } => {
+                attributes.policy = SCHED_DEADLINE;
+                attributes.runtime = runtime;
+                attributes.deadline = deadline;
+                attributes.period = period;
+            }
+        }
+        attributes
+    }
+
+    fn to_priority(&self) -> Result<Priority, Errno> {
+        let priority = u8::try_from(self.priority)
+            .ok()
+            .filter(|x| (1..=MAXIMUM_PRIORITY).contains(x));
+        let class = match self.policy {
+            SCHED_OTHER if self.priority == 0 => Class::Normal,
+            SCHED_FIFO => Class::Fifo(priority.ok_or(Errno::Invalid)?),
+            SCHED_RR => Class::RoundRobin(priority.ok_or(Errno::Invalid)?),
+            SCHED_DEADLINE => {
+                let period = if self.period == 0 {
+                    self.deadline
+                } else {
+                    self.period
+                };
+                if self.runtime == 0 || self.runtime > self.deadline || self.deadline > period {
+                    return Err(Errno::Invalid);
+                }
+                Class::Deadline {
+                    runtime: self.runtime,
+                    deadline: self.deadline,
+                    period,
+                }
+            }
+            _ => return Err(Errno::Invalid),
+        };
+        let nice = self.nice.clamp(MINIMUM_NICE, MAXIMUM_NICE);
+        Ok(Priority {
+            class,
+            nice: i8::try_from(nice).unwrap(),
+        })
+    }
+}
+
+#[derive(Clone, Copy, Default)]
+#[repr(C)]
+pub struct SigAction {
+    pub handler: u64,
+    pub flags: u64,
+    pub restorer: u64,
+    pub mask: u64,
+}
+
+impl SigAction {
+    fn from_action(action: signal::Action) -> SigAction {
+        match action {
+            signal::Action::Default => SigAction::default(),
+            signal::Action::Ignore => SigAction {
+                handler: SIG_IGN,
+                ..SigAction::default()
+            },
+            signal::Action::Handle(handler) => SigAction {
+                handler: handler.address,
+                flags: handler.flags,
+                restorer: handler.restorer,
+                mask: handler.mask,
+            },
+        }
+    }
+
+    fn to_action(self) -> Result<signal::Action, Errno> {
+        match self.handler {
+            SIG_DFL => Ok(signal::Action::Default),
+            SIG_IGN => Ok(signal::Action::Ignore),
+            _ if self.flags & SA_RESTORER == 0 => Err(Errno::Invalid),
+            address => Ok(signal::Action::Handle(Handler {
+                address,
+                restorer: self.restorer,
+                mask: self.mask,
+                flags: self.flags,
+            })),
+        }
+    }
+}
+
+pub struct Itimerval {
+    pub interval: Timespec,
+    pub value: Timespec,
+}
+
+type SystemCall = fn(&Arguments) -> Result<u64, Errno>;
+
+const TABLE: [Option<SystemCall>; SYSTEM_CALLS] = {
+    let mut table: [Option<SystemCall>; SYSTEM_CALLS] = [None; SYSTEM_CALLS];
+    table[SYS_WRITE] = Some(sys_write);
+    table[SYS_MPROTECT] = Some(sys_mprotect);
+    table[SYS_MUNMAP] = Some(sys_munmap);
+    table[SYS_RT_SIGACTION] = Some(sys_rt_sigaction);
+    table[SYS_RT_SIGPROCMASK] = Some(sys_rt_sigprocmask);
+    table[SYS_RT_SIGRETURN] = Some(|x| {
+        let context = sigreturn(x.context);
+        Ok(unsafe { (*(context as *const Context)).rax })
+    });
+    table[SYS_SCHED_YIELD] = Some(|_| {
+        thread::yield_now();
+        Ok(0)
+    });
+    table[SYS_NANOSLEEP] = Some(sys_nanosleep);
+    table[SYS_GETITIMER] = Some(sys_getitimer);
+    table[SYS_ALARM] = Some(|x| Ok(alarm(u64::from(x.get_u32(0)))));
+    table[SYS_SETITIMER] = Some(sys_setitimer);
+    table[SYS_GETPID] = Some(|_| Ok(getpid()));
+    table[SYS_CLONE] = Some(sys_clone);
+    table[SYS_FORK] = Some(|x| fork(x.context));
+    table[SYS_EXIT] = Some(sys_exit);
+    table[SYS_WAIT4] = Some(sys_wait4);
+    table[SYS_KILL] = Some(sys_kill);
+    table[SYS_GETRLIMIT] = Some(sys_getrlimit);
+    table[SYS_RT_SIGPENDING] = Some(sys_rt_sigpending);
+    table[SYS_GETPRIORITY] = Some(sys_getpriority);
+    table[SYS_SETPRIORITY] = Some(sys_setpriority);
+    table[SYS_SCHED_SETSCHEDULER] = Some(sys_sched_setscheduler);
+    table[SYS_SCHED_GETSCHEDULER] = Some(sys_sched_getscheduler);
+    table[SYS_ARCH_PRCTL] = Some(sys_arch_prctl);
+    table[SYS_SETRLIMIT] = Some(sys_setrlimit);
+    table[SYS_GETTID] = Some(|_| Ok(gettid()));
+    table[SYS_FUTEX] = Some(sys_futex);
+    table[SYS_SCHED_SETAFFINITY] = Some(sys_sched_setaffinity);
+    table[SYS_SCHED_GETAFFINITY] = Some(sys_sched_getaffinity);
+    table[SYS_CLOCK_GETTIME] = Some(sys_clock_gettime);
+    table[SYS_EXIT_GROUP] = Some(sys_exit_group);
+    table[SYS_PRLIMIT64] = Some(sys_prlimit64);
+    table
+};
+
+struct Arguments {
+    context: u64,
+    values: [u64; 6],
+}
+
+impl Arguments {
+    fn new(context: u64) -> Arguments {
+        let registers = unsafe { &*(context as *const Context) };
+        Arguments {
+            context,
+            values: [
+                registers.rdi,
+                registers.rsi,
+                registers.rdx,
+                registers.r10,
+                registers.r8,
+                registers.r9,
+            ],
+        }
+    }
+
+    fn get(&self, index: usize) -> u64 {
+        self.values[index]
+    }
+
+    fn get_u32(&self, index: usize) -> u32 {
+        u32::try_from(self.values[index] & u64::from(u32::MAX)).unwrap()
+    }
+
+    fn get_i32(&self, index: usize) -> i32 {
+        i32::from_ne_bytes(self.get_u32(index).to_ne_bytes())
+    }
+}
+
+fn get_user<T: Copy>(address: u64) -> Result<T, Errno> {
+    let size = mem::size_of::<T>() as u64;
+    if !memory::is_user_accessible(address, size, PageTableFlags::empty()) {
+        return Err(Errno::Fault);
+    }
+    Ok(unsafe { ptr::read_unaligned(address as *const T) })
+}
+
+fn get_user_timespec(address: u64) -> Result<Timespec, Errno> {
+    let [seconds, nanoseconds] = get_user::<[u64; 2]>(address)?;
+    if i64::try_from(seconds).is_err() {
+        return Err(Errno::Invalid);
+    }
+    let nanoseconds = u32::try_from(nanoseconds)
+        .ok()
+        .filter(|x| *x < 1_000_000_000)
+        .ok_or(Errno::Invalid)?;
+    Ok(Timespec {
+        seconds,
+        nanoseconds,
+    })
+}
+
+fn put_user_timespec(address: u64, time: &Timespec) -> Result<(), Errno> {
+    put_user(address, [time.seconds, u64::from(time.nanoseconds)])
+}
+
+fn get_user_timeval(address: u64) -> Result<Timespec, Errno> {
+    let [seconds, microseconds] = get_user::<[u64; 2]>(address)?;
+    if i64::try_from(seconds).is_err() {
+        return Err(Errno::Invalid);
+    }
+    let microseconds = u32::try_from(microseconds)
+        .ok()
+        .filter(|x| *x < 1_000_000)
+        .ok_or(Errno::Invalid)?;
+    Ok(Timespec {
+        seconds,
+        nanoseconds: microseconds * 1000,
+    })
+}
+
+fn put_user_timeval(address: u64, time: &Timespec) -> Result<(), Errno> {
+    put_user(address, [time.seconds, u64::from(time.nanoseconds / 1000)])
+}
+
+fn put_user<T>(address: u64, value: T) -> Result<(), Errno> {
+    let size = mem::size_of::<T>() as u64;
+    if !memory::is_user_accessible(address, size, PageTableFlags::WRITABLE) {
+        return Err(Errno::Fault);
+    }
+    unsafe {
+        ptr::write_unaligned(address as *mut T, value);
+    }
+    Ok(())
+}
+
+fn get_address_space() -> Result<PhysFrame, Errno> {
+    PROCESSES
+        .lock()
+        .get(process::get_current())
+        .map(process::Process::get_address_space)
+        .ok_or(Errno::NoSuchProcess)
+}
+
+fn resolve(pid: u64) -> u64 {
+    if pid == 0 {
+        process::get_current()
+    } else {
+        pid
+    }
+}
+
+pub fn alarm(seconds: u64) -> u64 {
+    let value = Timespec {
+        seconds,
+        nanoseconds: 0,
+    };
+    let interval = Timespec {
+        seconds: 0,
+        nanoseconds: 0,
+    };
+    let old = setitimer(&Itimerval { interval, value });
+    old.value.seconds + u64::from(old.value.nanoseconds != 0)
+}
+
+pub fn arch_prctl(code: u32, address: u64) -> Result<(), Errno> {
+    let pid = process::get_current();
+    match code {
+        ARCH_SET_FS => {
+            let base = VirtAddr::try_new(address).map_err(|_| Errno::Invalid)?;
+            FsBase::write(base);
+            if let Some(process) = PROCESSES.lock().get_mut(pid) {
+                process.set_fs_base(address);
+            }
+            Ok(())
+        }
+        ARCH_GET_FS => put_user(address, FsBase::read().as_u64()),
+        _ => Err(Errno::Invalid),
+    }
+}
+
+pub fn clone(
+    context: u64,
+    flags: u64,
+    stack: u64,
+    parent_tid: u64,
+    child_tid: u64,
+    tls: u64,
+) -> Result<u64, Errno> {
+    if flags & CLONE_THREAD_FLAGS != CLONE_THREAD_FLAGS
+        || flags & CLONE_FILES != 0
+        || !process::is_user(context)
+    {
+        return Err(Errno::Invalid);
+    }
+    if flags & CLONE_SETTLS != 0 && VirtAddr::try_new(tls).is_err() {
+        return Err(Errno::Invalid);
+    }
+    let tls = Some(tls).filter(|_| flags & CLONE_SETTLS != 0);
+    let clear = if flags & CLONE_CHILD_CLEARTID == 0 {
+        0
+    } else {
+        child_tid
+    };
+    let size = mem::size_of::<u32>() as u64;
+    let writable = |address| memory::is_user_accessible(address, size, PageTableFlags::WRITABLE);
+    if (flags & CLONE_PARENT_SETTID != 0 && !writable(parent_tid))
+        || (flags & CLONE_CHILD_SETTID != 0 && !writable(child_tid))
+    {
+        return Err(Errno::Fault);
+    }
+    let tid = scheduler::clone(context, stack, tls, clear)?;
+    let id = u32::try_from(tid).unwrap();
+    if flags & CLONE_PARENT_SETTID != 0 {
+        put_user(parent_tid, id)?;
+    }
+    if flags & CLONE_CHILD_SETTID != 0 {
+        put_user(child_tid, id)?;
+    }
+    scheduler::place(tid);
+    Ok(tid)
+}
+
+pub fn clock_gettime(clock: ClockId) -> Option<Timespec> {
+    let time = match clock {
+        ClockId::Realtime => TIMER.get_realtime()?,
+        ClockId::Monotonic => TIMER.now(),
+    };
+    Some(Timespec::from_duration(time))
+}
+
+pub fn getitimer() -> Itimerval {
+    let table = PROCESSES.lock();
+    let (value, interval) = table
+        .get(process::get_current())
+        .and_then(process::Process::get_alarm)
+        .and_then(timer::remaining)
+        .unwrap_or_default();
+    Itimerval {
+        interval: Timespec::from_duration(interval.unwrap_or_default()),
+        value: Timespec::from_duration(value),
+    }
+}
+
+pub fn getpid() -> u64 {
+    let pid = process::get_current();
+    PROCESSES
+        .lock()
+        .get(pid)
+        .map_or(pid, process::Process::get_thread_group)
+}
+
+pub fn gettid() -> u64 {
+    process::get_current()
+}
+
+pub fn getrlimit(resource: u32) -> Result<Limit, Errno> {
+    prlimit(0, resource, None)
+}
+
+pub fn getpriority(which: u32, who: u64) -> Result<i32, Errno> {
+    if which != PRIO_PROCESS {
+        return Err(Errno::Invalid);
+    }
+    let table = PROCESSES.lock();
+    let process = table.get(resolve(who)).ok_or(Errno::NoSuchProcess)?;
+    Ok(i32::from(process.get_priority().nice))
+}
+
+pub fn futex(
+    address: u64,
+    operation: u32,
+    value: u32,
+    timeout: Option<&Timespec>,
+) -> Result<u32, Errno> {
+    match operation & !FUTEX_PRIVATE_FLAG {
+        FUTEX_WAIT => {
+            futex::wait(address, value, timeout.map(Timespec::to_duration))?;
+            Ok(0)
+        }
+        FUTEX_WAKE => futex::wake(address, value),
+        _ => Err(Errno::Invalid),
+    }
+}
+
+pub fn mprotect(address: u64, length: u64, protection: u32) -> Result<(), Errno> {
+    if address % PAGE_SIZE as u64 != 0 || protection & !(PROT_READ | PROT_WRITE | PROT_EXEC) != 0 {
+        return Err(Errno::Invalid);
+    }
+    if length == 0 {
+        return Ok(());
+    }
+    let mut flags = PageTableFlags::empty();
+    if protection != 0 {
+        flags |= PageTableFlags::USER_ACCESSIBLE;
+    }
+    if protection & PROT_WRITE != 0 {
+        flags |= PageTableFlags::WRITABLE;
+    }
+    if protection & PROT_EXEC == 0 {
+        flags |= PageTableFlags::NO_EXECUTE;
+    }
+    memory::protect_user(get_address_space()?, address, length, flags)
+}
+
+pub fn munmap(address: u64, length: u64) -> Result<(), Errno> {
+    if address % PAGE_SIZE as u64 != 0 || length == 0 {
+        return Err(Errno::Invalid);
+    }
+    memory::unmap_user(get_address_space()?, address, length).map_err(|_| Errno::Invalid)
+}
+
+pub fn nanosleep(request: &Timespec, remaining: Option<&mut Timespec>) -> Result<(), Errno> {
+    let left = timer::sleep(request.to_duration());
+    if left.is_zero() {
+        return Ok(());
+    }
+    if let Some(remaining) = remaining {
+        *remaining = Timespec::from_duration(left);
+    }
+    Err(Errno::Interrupted)
+}
+
+pub fn prlimit(pid: u64, resource: u32, new: Option<&Limit>) -> Result<Limit, Errno> {
+    let resource = Resource::from_u32(resource).ok_or(Errno::Invalid)?;
+    let mut table = PROCESSES.lock();
+    let process = table.get(resolve(pid)).ok_or(Errno::NoSuchProcess)?;
+    let old = process.get_limits().get(resource);
+    if let Some(limit) = new {
+        let group = process.get_thread_group();
+        let threads: Vec<u64> = table
+            .iter()
+            .filter(|x| x.get_thread_group() == group)
+            .map(process::Process::get_id)
+            .collect();
+        for thread in threads {
+            if let Some(process) = table.get_mut(thread) {
+                process.get_limits_mut().set(resource, *limit)?;
+            }
+        }
+    }
+    Ok(old)
+}
+
+pub fn setpriority(which: u32, who: u64, priority: i32) -> Result<(), Errno> {
+    if which != PRIO_PROCESS {
+        return Err(Errno::Invalid);
+    }
+    let mut table = PROCESSES.lock();
+    let process = table.get_mut(resolve(who)).ok_or(Errno::NoSuchProcess)?;
+    let mut attributes = process.get_priority();
+    let nice = priority.clamp(MINIMUM_NICE, MAXIMUM_NICE);
+    attributes.nice = i8::try_from(nice).unwrap();
+    process.set_priority(attributes);
+    Ok(())
+}
+
+pub fn setrlimit(resource: u32, limit: &Limit) -> Result<(), Errno> {
+    prlimit(0, resource, Some(limit)).map(|_| ())
+}
+
+pub fn sched_getaffinity(pid: u64) -> Result<u64, Errno> {
+    let table = PROCESSES.lock();
+    let process = table.get(resolve(pid)).ok_or(Errno::NoSuchProcess)?;
+    Ok(process.get_affinity())
+}
+
+pub fn sched_getattr(pid: u64) -> Result<SchedAttr, Errno> {
+    let table = PROCESSES.lock();
+    let process = table.get(resolve(pid)).ok_or(Errno::NoSuchProcess)?;
+    Ok(SchedAttr::from_priority(process.get_priority()))
+}
+
+pub fn sched_getscheduler(pid: u64) -> Result<u32, Errno> {
+    Ok(sched_getattr(pid)?.policy)
+}
+
+pub fn sched_setaffinity(pid: u64, mask: u64) -> Result<(), Errno> {
+    let online = cpu::get_cpus()
+        .iter()
+        .any(|x| scheduler::is_allowed(mask, x.get_id()));
+    if !online {
+        return Err(Errno::Invalid);
+    }
+    let pid = resolve(pid);
+    let mut table = PROCESSES.lock();
+    let process = table.get_mut(pid).ok_or(Errno::NoSuchProcess)?;
+    process.set_affinity(mask);
+    drop(table);
+    scheduler::migrate(pid);
+    Ok(())
+}
+
+pub fn sched_setattr(pid: u64, attributes: &SchedAttr) -> Result<(), Errno> {
+    let priority = attributes.to_priority()?;
+    let pid = resolve(pid);
+    let mut table = PROCESSES.lock();
+    let process = table.get_mut(pid).ok_or(Errno::NoSuchProcess)?;
+    if !realtime::admit(process.get_priority().class, priority.class) {
+        return Err(Errno::Busy);
+    }
+    process.set_priority(priority);
+    let queued = !process.is_on_cpu() && matches!(process.get_state(), State::Ready);
+    let cpu = process.get_cpu();
+    drop(table);
+    if let Some(cpu) = cpu::get(cpu).filter(|_| queued) {
+        cpu.get_scheduler().lock().requeue(pid, priority);
+    }
+    Ok(())
+}
+
+pub fn sched_setscheduler(pid: u64, policy: u32, parameters: &SchedParam) -> Result<(), Errno> {
+    if policy == SCHED_DEADLINE {
+        return Err(Errno::Invalid);
+    }
+    let attributes = SchedAttr {
+        policy,
+        nice: sched_getattr(pid)?.nice,
+        priority: parameters.priority,
+        runtime: 0,
+        deadline: 0,
+        period: 0,
+    };
+    sched_setattr(pid, &attributes)
+}
+
+pub fn sigaction(
+    signal: u32,
+    new: Option<&SigAction>,
+    old: Option<&mut SigAction>,
+) -> Result<(), Errno> {
+    if !signal::is_catchable(signal) && (new.is_some() || !signal::is_valid(signal)) {
+        return Err(Errno::Invalid);
+    }
+    let action = new.map(|x| x.to_action()).transpose()?;
+    let mut table = PROCESSES.lock();
+    let process = table
+        .get_mut(process::get_current())
+        .ok_or(Errno::NoSuchProcess)?;
+    let signals = process.get_signals_mut();
+    if let Some(old) = old {
+        *old = SigAction::from_action(signals.get_action(signal));
+    }
+    if let Some(action) = action {
+        signals.set_action(signal, action);
+    }
+    Ok(())
+}
+
+pub fn sigpending() -> u64 {
+    let table = PROCESSES.lock();
+    table.get(process::get_current()).map_or(0, |x| {
+        let signals = x.get_signals();
+        signals.get_pending() & signals.get_blocked()
+    })
+}
+
+pub fn sigprocmask(how: u32, set: Option<u64>, old: Option<&mut u64>) -> Result<(), Errno> {
+    let mut table = PROCESSES.lock();
+    let process = table
+        .get_mut(process::get_current())
+        .ok_or(Errno::NoSuchProcess)?;
+    let signals = process.get_signals_mut();
+    let blocked = signals.get_blocked();
+    if let Some(old) = old {
+        *old = blocked;
+    }
+    if let Some(set) = set {
+        let mask = match how {
+            SIG_BLOCK => blocked | set,
+            SIG_UNBLOCK => blocked & !set,
+            SIG_SETMASK => set,
+            _ => return Err(Errno::Invalid),
+        };
+        signals.set_blocked(mask);
+    }
+    Ok(())
+}
+
+pub fn sigreturn(context: u64) -> u64 {
+    signal::restore(context)
+}
+
+pub fn setitimer(new: &Itimerval) -> Itimerval {
+    let mut table = PROCESSES.lock();
+    let process = table.get_mut(process::get_current()).unwrap();
+    let (value, interval) = process
+        .get_alarm()
+        .and_then(timer::cancel)
+        .unwrap_or_default();
+    let duration = new.value.to_duration();
+    let id = if duration.is_zero() {
+        None
+    } else {
+        let period = Some(new.interval.to_duration()).filter(|x| !x.is_zero());
+        Some(timer::schedule(
+            duration,
+            period,
+            Action::Callback(scheduler::alarm, process.get_id()),
+        ))
+    };
+    process.set_alarm(id);
+    Itimerval {
+        interval: Timespec::from_duration(interval.unwrap_or_default()),
+        value: Timespec::from_duration(value),
+    }
+}
+
+pub fn exit(status: i32) -> ! {
+    process::exit(status);
+}
+
+pub fn exit_group(status: i32) -> ! {
+    process::exit_group(status);
+}
+
+pub fn fork(context: u64) -> Result<u64, Errno> {
+    scheduler::fork(context)
+}
+
+pub fn kill(pid: i64, signal: u32) -> Result<(), Errno> {
+    let pid = u64::try_from(pid)
+        .ok()
+        .filter(|x| *x != 0)
+        .ok_or(Errno::Invalid)?;
+    signal::send(pid, signal)
+}
+
+pub fn waitpid(pid: i64, status: &mut i32, options: u32) -> Result<u64, Errno> {
+    let pid = u64::try_from(pid).ok();
+    match process::wait(pid, options & WNOHANG == 0)? {
+        Some((child, code)) => {
+            *status = code;
+            Ok(child)
+        }
+        None => Ok(0),
+    }
+}
+
+pub fn write(descriptor: u64, buffer: &[u8]) -> Result<u64, Errno> {
+    if descriptor != STDOUT && descriptor != STDERR {
+        return Err(Errno::BadDescriptor);
+    }
+    let text = String::from_utf8_lossy(buffer);
+    let _ = SERIAL.lock().write_str(&text);
+    let _ = VGA.lock().write_str(&text);
+    Ok(buffer.len() as u64)
+}
+
+fn sys_write(arguments: &Arguments) -> Result<u64, Errno> {
+    let (address, count) = (arguments.get(1), arguments.get(2));
+    if count == 0 {
+        return Ok(0);
+    }
+    if !memory::is_user_accessible(address, count, PageTableFlags::empty()) {
+        return Err(Errno::Fault);
+    }
+    let length = usize::try_from(count).map_err(|_| Errno::Invalid)?;
+    let buffer = unsafe { slice::from_raw_parts(address as *const u8, length) };
+    write(arguments.get(0), buffer)
+}
+
+fn sys_mprotect(arguments: &Arguments) -> Result<u64, Errno> {
+    mprotect(arguments.get(0), arguments.get(1), arguments.get_u32(2))?;
+    Ok(0)
+}
+
+fn sys_munmap(arguments: &Arguments) -> Result<u64, Errno> {
+    munmap(arguments.get(0), arguments.get(1))?;
+    Ok(0)
+}
+
+fn sys_rt_sigaction(arguments: &Arguments) -> Result<u64, Errno> {
+    if arguments.get(3) != mem::size_of::<u64>() as u64 {
+        return Err(Errno::Invalid);
+    }
+    let new = match arguments.get(1) {
+        0 => None,
+        address => Some(get_user::<SigAction>(address)?),
+    };
+    let mut old = SigAction::default();
+    sigaction(arguments.get_u32(0), new.as_ref(), Some(&mut old))?;
+    if arguments.get(2) != 0 {
+        put_user(arguments.get(2), old)?;
+    }
+    Ok(0)
+}
+
+fn sys_rt_sigprocmask(arguments: &Arguments) -> Result<u64, Errno> {
+    if arguments.get(3) != mem::size_of::<u64>() as u64 {
+        return Err(Errno::Invalid);
+    }
+    let set = match arguments.get(1) {
+        0 => None,
+        address => Some(get_user::<u64>(address)?),
+    };
+    let mut old = 0;
+    sigprocmask(arguments.get_u32(0), set, Some(&mut old))?;
+    if arguments.get(2) != 0 {
+        put_user(arguments.get(2), old)?;
+    }
+    Ok(0)
+}
+
+fn sys_nanosleep(arguments: &Arguments) -> Result<u64, Errno> {
+    let request = get_user_timespec(arguments.get(0))?;
+    let mut remaining = Timespec::default();
+    let result = nanosleep(&request, Some(&mut remaining));
+    if matches!(result, Err(Errno::Interrupted)) && arguments.get(1) != 0 {
+        put_user_timespec(arguments.get(1), &remaining)?;
+    }
+    result.map(|()| 0)
+}
+
+fn sys_getitimer(arguments: &Arguments) -> Result<u64, Errno> {
+    if arguments.get_u32(0) != ITIMER_REAL {
+        return Err(Errno::Invalid);
+    }
+    let current = getitimer();
+    put_user_timeval(arguments.get(1), &current.interval)?;
+    put_user_timeval(arguments.get(1) + 16, &current.value)?;
+    Ok(0)
+}
+
+fn sys_setitimer(arguments: &Arguments) -> Result<u64, Errno> {
+    if arguments.get_u32(0) != ITIMER_REAL {
+        return Err(Errno::Invalid);
+    }
+    let new = Itimerval {
+        interval: get_user_timeval(arguments.get(1))?,
+        value: get_user_timeval(arguments.get(1) + 16)?,
+    };
+    let old = setitimer(&new);
+    if arguments.get(2) != 0 {
+        put_user_timeval(arguments.get(2), &old.interval)?;
+        put_user_timeval(arguments.get(2) + 16, &old.value)?;
+    }
+    Ok(0)
+}
+
+fn sys_clone(arguments: &Arguments) -> Result<u64, Errno> {
+    clone(
+        arguments.context,
+        arguments.get(0),
+        arguments.get(1),
+        arguments.get(2),
+        arguments.get(3),
+        arguments.get(4),
+    )
+}
+
+fn sys_exit(arguments: &Arguments) -> Result<u64, Errno> {
+    exit(arguments.get_i32(0) & 0xFF);
+}
+
+fn sys_wait4(arguments: &Arguments) -> Result<u64, Errno> {
+    let pid = arguments.get_i32(0);
+    if pid == 0 || pid < -1 {
+        return Err(Errno::Invalid);
+    }
+    let mut status = 0;
+    let child = waitpid(i64::from(pid), &mut status, arguments.get_u32(2))?;
+    if child != 0 && arguments.get(1) != 0 {
+        put_user(arguments.get(1), (status & 0xFF) << 8)?;
+    }
+    Ok(child)
+}
+
+fn sys_kill(arguments: &Arguments) -> Result<u64, Errno> {
+    kill(i64::from(arguments.get_i32(0)), arguments.get_u32(1))?;
+    Ok(0)
+}
+
+fn sys_getrlimit(arguments: &Arguments) -> Result<u64, Errno> {
+    put_user(arguments.get(1), getrlimit(arguments.get_u32(0))?)?;
+    Ok(0)
+}
+
+fn sys_rt_sigpending(arguments: &Arguments) -> Result<u64, Errno> {
+    put_user(arguments.get(0), sigpending())?;
+    Ok(0)
+}
+
+fn sys_getpriority(arguments: &Arguments) -> Result<u64, Errno> {
+    let nice = getpriority(arguments.get_u32(0), u64::from(arguments.get_u32(1)))?;
+    Ok(u64::from((NICE_OFFSET - nice).unsigned_abs()))
+}
+
+fn sys_setpriority(arguments: &Arguments) -> Result<u64, Errno> {
+    let who = u64::from(arguments.get_u32(1));
+    setpriority(arguments.get_u32(0), who, arguments.get_i32(2))?;
+    Ok(0)
+}
+
+fn sys_sched_setscheduler(arguments: &Arguments) -> Result<u64, Errno> {
+    let priority = get_user::<u32>(arguments.get(2))?;
+    let pid = u64::from(arguments.get_u32(0));
+    sched_setscheduler(pid, arguments.get_u32(1), &SchedParam { priority })?;
+    Ok(0)
+}
+
+fn sys_sched_getscheduler(arguments: &Arguments) -> Result<u64, Errno> {
+    Ok(u64::from(sched_getscheduler(u64::from(
+        arguments.get_u32(0),
+    ))?))
+}
+
+fn sys_arch_prctl(arguments: &Arguments) -> Result<u64, Errno> {
+    arch_prctl(arguments.get_u32(0), arguments.get(1))?;
+    Ok(0)
 }
 
-pub fn exec() {
-    todo!("Implement system call.");
+fn sys_setrlimit(arguments: &Arguments) -> Result<u64, Errno> {
+    let limit = get_user::<Limit>(arguments.get(1))?;
+    setrlimit(arguments.get_u32(0), &limit)?;
+    Ok(0)
 }
 
-pub fn exit() {
-    todo!("Implement system call.");
+fn sys_futex(arguments: &Arguments) -> Result<u64, Errno> {
+    let timeout = match arguments.get(3) {
+        0 => None,
+        address => Some(get_user_timespec(address)?),
+    };
+    let woken = futex(
+        arguments.get(0),
+        arguments.get_u32(1),
+        arguments.get_u32(2),
+        timeout.as_ref(),
+    )?;
+    Ok(u64::from(woken))
 }
 
-pub fn fork() -> u64 {
-    SCHEDULER.lock().fork()
+fn sys_sched_setaffinity(arguments: &Arguments) -> Result<u64, Errno> {
+    if arguments.get(1) < mem::size_of::<u64>() as u64 {
+        return Err(Errno::Invalid);
+    }
+    let mask = get_user::<u64>(arguments.get(2))?;
+    sched_setaffinity(u64::from(arguments.get_u32(0)), mask)?;
+    Ok(0)
 }
 
-pub fn kill() {
-    todo!("Implement system call.");
+fn sys_sched_getaffinity(arguments: &Arguments) -> Result<u64, Errno> {
+    let size = mem::size_of::<u64>() as u64;
+    if arguments.get(1) < size {
+        return Err(Errno::Invalid);
+    }
+    let mask = sched_getaffinity(u64::from(arguments.get_u32(0)))?;
+    put_user(arguments.get(2), mask)?;
+    Ok(size)
 }
 
-pub fn open() {
-    todo!("Implement system call.");
+fn sys_clock_gettime(arguments: &Arguments) -> Result<u64, Errno> {
+    let clock = match arguments.get_u32(0) {
+        CLOCK_REALTIME => ClockId::Realtime,
+        CLOCK_MONOTONIC => ClockId::Monotonic,
+        _ => return Err(Errno::Invalid),
+    };
+    let time = clock_gettime(clock).ok_or(Errno::Invalid)?;
+    put_user_timespec(arguments.get(1), &time)?;
+    Ok(0)
 }
 
-pub fn read() {
-    todo!("Implement system call.");
+fn sys_exit_group(arguments: &Arguments) -> Result<u64, Errno> {
+    exit_group(arguments.get_i32(0) & 0xFF);
 }
 
-pub fn wait() {
-    todo!("Implement system call.");
+fn sys_prlimit64(arguments: &Arguments) -> Result<u64, Errno> {
+    let new = match arguments.get(2) {
+        0 => None,
+        address => Some(get_user::<Limit>(address)?),
+    };
+    let pid = u64::from(arguments.get_u32(0));
+    let old = prlimit(pid, arguments.get_u32(1), new.as_ref())?;
+    if arguments.get(3) != 0 {
+        put_user(arguments.get(3), old)?;
+    }
+    Ok(0)
 }
 
-pub fn write() {
-    todo!("Implement system call.");
+pub fn dispatch(context: u64) {
+    let registers = unsafe { &mut *(context as *mut Context) };
+    let number = registers.rax;
+    let handler = usize::try_from(number)
+        .ok()
+        .and_then(|x| TABLE.get(x).copied().flatten());
+    let arguments = Arguments::new(context);
+    interrupts::enable();
+    let result = handler.map_or(Err(Errno::NoSystemCall), |x| x(&arguments));
+    interrupts::disable();
+    if number == SYS_RT_SIGRETURN as u64 {
+        return;
+    }
+    registers.rax = match result {
+        Ok(value) => value,
+        Err(errno) => 0u64.wrapping_sub(errno as u64),
+    };
 }
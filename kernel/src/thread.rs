@@ -0,0 +1,93 @@
+// NeurOS - Hobbyist operating system written in Rust.
+// Copyright (C) 2024 Theomund
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program. If not, see <https://www.gnu.org/licenses/>.
+
+use crate::blocking::Completion;
+use crate::debug;
+use crate::interrupts::InterruptIndex;
+use crate::logger::{Level, LOGGER};
+use crate::process::{self, Process, PROCESSES};
+use crate::scheduler;
+use crate::sync::IrqMutex;
+use alloc::boxed::Box;
+use alloc::format;
+use alloc::sync::Arc;
+use core::arch::asm;
+
+type Task = Box<dyn FnOnce() + Send>;
+
+struct Packet<T> {
+    result: IrqMutex<Option<T>>,
+    finished: Completion,
+}
+
+pub struct JoinHandle<T> {
+    id: u64,
+    packet: Arc<Packet<T>>,
+}
+
+impl<T> JoinHandle<T> {
+    pub fn get_id(&self) -> u64 {
+        self.id
+    }
+
+    pub fn join(self) -> Option<T> {
+        self.packet.finished.wait();
+        self.packet.result.lock().take()
+    }
+}
+
+extern "C" fn start(argument: u64) -> ! {
+    let task = unsafe { Box::from_raw(argument as *mut Task) };
+    task();
+    exit();
+}
+
+pub fn spawn<F, T>(name: &str, function: F) -> JoinHandle<T>
+where
+    F: FnOnce() -> T + Send + 'static,
+    T: Send + 'static,
+{
+    let packet = Arc::new(Packet {
+        result: IrqMutex::new("THREAD", None),
+        finished: Completion::new("THREAD_FINISHED"),
+    });
+    let output = packet.clone();
+    let task: Task = Box::new(move || {
+        let value = function();
+        *output.result.lock() = Some(value);
+        output.finished.complete_all();
+    });
+    let argument = Box::into_raw(Box::new(task)) as u64;
+    let mut table = PROCESSES.lock();
+    let id = table
+        .allocate()
+        .expect("Failed to allocate a kernel thread ID.");
+    table.insert(Process::spawn(id, name, start, argument));
+    drop(table);
+    scheduler::place(id);
+    debug!("Spawned kernel thread #{id} ({name}).");
+    JoinHandle { id, packet }
+}
+
+pub fn yield_now() {
+    unsafe {
+        asm!("int {}", const InterruptIndex::Yield as u8);
+    }
+}
+
+pub fn exit() -> ! {
+    process::exit(0);
+}
@@ -14,27 +14,356 @@
 // You should have received a copy of the GNU General Public License
 // along with this program. If not, see <https://www.gnu.org/licenses/>.
 
-use core::sync::atomic::{AtomicU32, Ordering};
-use spin::Lazy;
+use crate::apic::LAPIC;
+use crate::hpet::HPET;
+use crate::logger::{Level, LOGGER};
+use crate::pit::PIT;
+use crate::process::{self, State};
+use crate::sync::IrqMutex;
+use crate::{apic, cpu, hpet, info, scheduler, signal, tsc};
+use alloc::collections::BinaryHeap;
+use alloc::format;
+use alloc::vec;
+use alloc::vec::Vec;
+use core::cmp::{Ordering as Order, Reverse};
+use core::sync::atomic::{AtomicU64, Ordering};
+use core::time::Duration;
+use spin::{Lazy, Once};
+
+pub const FREQUENCY: u32 = 1000;
+
+const MAXIMUM_IDLE: u64 = 1_000_000_000;
 
 pub static TIMER: Lazy<Timer> = Lazy::new(Timer::new);
 
+static QUEUE: Lazy<IrqMutex<TimerQueue>> = Lazy::new(|| {
+    let queue = TimerQueue::new();
+    IrqMutex::new("TIMER_QUEUE", queue)
+});
+
+pub trait ClockSource: Sync {
+    fn get_name(&self) -> &'static str;
+    fn get_rating(&self) -> u32;
+    fn get_nanoseconds(&self) -> u64;
+
+    fn is_tick_based(&self) -> bool {
+        false
+    }
+}
+
+pub trait ClockEvent: Sync {
+    fn get_name(&self) -> &'static str;
+    fn get_rating(&self) -> u32;
+    fn start_periodic(&self, frequency: u32) -> u64;
+    fn start_oneshot(&self, nanoseconds: u64);
+    fn stop(&self);
+}
+
 pub struct Timer {
-    elapsed: AtomicU32,
+    elapsed: AtomicU64,
+    period: AtomicU64,
+    source: Once<&'static dyn ClockSource>,
+    event: Once<&'static dyn ClockEvent>,
+    realtime: Once<u64>,
 }
 
 impl Timer {
     pub fn new() -> Timer {
         Timer {
-            elapsed: AtomicU32::new(0),
+            elapsed: AtomicU64::new(0),
+            period: AtomicU64::new(0),
+            source: Once::new(),
+            event: Once::new(),
+            realtime: Once::new(),
         }
     }
 
-    pub fn get_elapsed(&self) -> u32 {
+    pub fn get_elapsed(&self) -> u64 {
         self.elapsed.load(Ordering::Relaxed)
     }
 
+    pub fn get_period(&self) -> u64 {
+        self.period.load(Ordering::Relaxed)
+    }
+
+    pub fn get_nanoseconds(&self) -> u64 {
+        match self.source.get() {
+            Some(source) => source.get_nanoseconds(),
+            None => self.get_elapsed() * self.get_period(),
+        }
+    }
+
+    pub fn get_milliseconds(&self) -> u64 {
+        self.get_nanoseconds() / 1_000_000
+    }
+
+    pub fn now(&self) -> Duration {
+        Duration::from_nanos(self.get_nanoseconds())
+    }
+
+    pub fn get_realtime(&self) -> Option<Duration> {
+        let offset = self.realtime.get()?;
+        Some(Duration::from_nanos(offset + self.get_nanoseconds()))
+    }
+
+    pub fn set_realtime(&self, seconds: u64) {
+        let nanoseconds = seconds * 1_000_000_000;
+        self.realtime
+            .call_once(|| nanoseconds.saturating_sub(self.get_nanoseconds()));
+    }
+
     pub fn increment(&self) {
         self.elapsed.fetch_add(1, Ordering::Relaxed);
     }
+
+    pub fn stop_tick(&self) {
+        let Some(event) = self.event.get() else {
+            return;
+        };
+        let tickless = self.source.get().is_some_and(|x| !x.is_tick_based());
+        let cpu = cpu::current();
+        let scheduler = cpu.get_scheduler().lock();
+        let quiet = scheduler.is_idle() && !scheduler.has_pending();
+        drop(scheduler);
+        if tickless && quiet {
+            let now = self.get_nanoseconds();
+            let timeout = QUEUE
+                .lock()
+                .next_deadline()
+                .map_or(MAXIMUM_IDLE, |x| x.saturating_sub(now).min(MAXIMUM_IDLE));
+            event.start_oneshot(timeout);
+            cpu.set_tick_stopped(true);
+        }
+    }
+
+    pub fn restart_tick(&self) {
+        if cpu::current().set_tick_stopped(false) {
+            if let Some(event) = self.event.get() {
+                event.start_periodic(FREQUENCY);
+            }
+        }
+    }
+
+    fn select_source(&self, sources: &[&'static dyn ClockSource]) {
+        let source = sources.iter().max_by_key(|x| x.get_rating()).unwrap();
+        self.source.call_once(|| *source);
+        info!(
+            "Selected {} as the clock source (rating {}).",
+            source.get_name(),
+            source.get_rating()
+        );
+    }
+
+    fn select_event(&self, events: &[&'static dyn ClockEvent]) {
+        let event = events.iter().max_by_key(|x| x.get_rating()).unwrap();
+        for other in events {
+            other.stop();
+        }
+        let period = event.start_periodic(FREQUENCY);
+        self.period.store(period, Ordering::Relaxed);
+        self.event.call_once(|| *event);
+        info!(
+            "Selected {} as the tick device (rating {}) with a period of {period} ns.",
+            event.get_name(),
+            event.get_rating()
+        );
+    }
+}
+
+#[derive(Clone)]
+pub enum Action {
+    Callback(fn(u64), u64),
+    Wake(u64),
+}
+
+impl Action {
+    fn fire(&self) {
+        match self {
+            Action::Callback(function, argument) => function(*argument),
+            Action::Wake(pid) => scheduler::wake(*pid),
+        }
+    }
+}
+
+struct Entry {
+    deadline: u64,
+    id: u64,
+    interval: Option<u64>,
+    action: Action,
+}
+
+impl PartialEq for Entry {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Order::Equal
+    }
+}
+
+impl Eq for Entry {}
+
+impl PartialOrd for Entry {
+    fn partial_cmp(&self, other: &Self) -> Option<Order> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Entry {
+    fn cmp(&self, other: &Self) -> Order {
+        (self.deadline, self.id).cmp(&(other.deadline, other.id))
+    }
+}
+
+struct TimerQueue {
+    entries: BinaryHeap<Reverse<Entry>>,
+    next: u64,
+}
+
+impl TimerQueue {
+    fn new() -> TimerQueue {
+        TimerQueue {
+            entries: BinaryHeap::new(),
+            next: 1,
+        }
+    }
+
+    fn add(&mut self, deadline: u64, interval: Option<u64>, action: Action) -> u64 {
+        let id = self.next;
+        self.next += 1;
+        self.entries.push(Reverse(Entry {
+            deadline,
+            id,
+            interval,
+            action,
+        }));
+        id
+    }
+
+    fn peek(&self, id: u64) -> Option<(u64, Option<u64>)> {
+        let entry = self.entries.iter().find(|x| x.0.id == id)?;
+        Some((entry.0.deadline, entry.0.interval))
+    }
+
+    fn cancel(&mut self, id: u64) -> Option<(u64, Option<u64>)> {
+        let result = self.peek(id)?;
+        self.entries.retain(|x| x.0.id != id);
+        Some(result)
+    }
+
+    fn next_deadline(&self) -> Option<u64> {
+        self.entries.peek().map(|x| x.0.deadline)
+    }
+
+    fn pop_expired(&mut self, now: u64) -> Option<Action> {
+        if self.next_deadline()? > now {
+            return None;
+        }
+        let Reverse(mut entry) = self.entries.pop()?;
+        let action = entry.action.clone();
+        if let Some(interval) = entry.interval {
+            entry.deadline = entry.deadline.saturating_add(interval).max(now + 1);
+            self.entries.push(Reverse(entry));
+        }
+        Some(action)
+    }
+}
+
+pub struct Timeout {
+    deadline: Option<u64>,
+}
+
+impl Timeout {
+    pub fn new(duration: Option<Duration>) -> Timeout {
+        let deadline = duration.map(|x| TIMER.get_nanoseconds().saturating_add(nanoseconds(x)));
+        Timeout { deadline }
+    }
+
+    pub fn expired(&self) -> bool {
+        self.deadline.is_some_and(|x| TIMER.get_nanoseconds() >= x)
+    }
+}
+
+fn nanoseconds(duration: Duration) -> u64 {
+    u64::try_from(duration.as_nanos()).unwrap_or(u64::MAX)
+}
+
+pub fn schedule(duration: Duration, interval: Option<Duration>, action: Action) -> u64 {
+    let deadline = TIMER
+        .get_nanoseconds()
+        .saturating_add(nanoseconds(duration));
+    QUEUE
+        .lock()
+        .add(deadline, interval.map(nanoseconds), action)
+}
+
+pub fn cancel(id: u64) -> Option<(Duration, Option<Duration>)> {
+    let (deadline, interval) = QUEUE.lock().cancel(id)?;
+    let remaining = deadline.saturating_sub(TIMER.get_nanoseconds());
+    Some((
+        Duration::from_nanos(remaining),
+        interval.map(Duration::from_nanos),
+    ))
+}
+
+pub fn remaining(id: u64) -> Option<(Duration, Option<Duration>)> {
+    let (deadline, interval) = QUEUE.lock().peek(id)?;
+    let remaining = deadline.saturating_sub(TIMER.get_nanoseconds());
+    Some((
+        Duration::from_nanos(remaining),
+        interval.map(Duration::from_nanos),
+    ))
+}
+
+pub fn expire() {
+    let now = TIMER.get_nanoseconds();
+    loop {
+        let action = QUEUE.lock().pop_expired(now);
+        match action {
+            Some(action) => action.fire(),
+            None => break,
+        }
+    }
+}
+
+pub fn sleep(duration: Duration) -> Duration {
+    let timeout = Timeout::new(Some(duration));
+    let id = schedule(duration, None, Action::Wake(process::get_current()));
+    scheduler::block_until(State::Sleeping, || {
+        timeout.expired() || signal::is_pending()
+    });
+    cancel(id).map_or(Duration::ZERO, |(remaining, _)| remaining)
+}
+
+pub fn milliseconds_to_ticks(milliseconds: u32) -> u32 {
+    let ticks = u64::from(milliseconds)
+        .checked_mul(u64::from(FREQUENCY))
+        .map_or(u64::MAX, |x| x.div_ceil(1000));
+    u32::try_from(ticks).unwrap_or(u32::MAX).max(1)
+}
+
+pub fn delay(nanoseconds: u64) {
+    match HPET.get() {
+        Some(hpet) => {
+            let deadline = hpet.get_nanoseconds() + nanoseconds;
+            while hpet.get_nanoseconds() < deadline {}
+        }
+        None => PIT.wait(nanoseconds),
+    }
+}
+
+pub fn initialize_cpu() {
+    if let Some(lapic) = LAPIC.get() {
+        lapic.start_periodic(FREQUENCY);
+    }
+}
+
+pub fn initialize() {
+    let mut sources: Vec<&'static dyn ClockSource> = vec![&PIT];
+    let mut events: Vec<&'static dyn ClockEvent> = vec![&PIT];
+    if let Some(hpet) = hpet::initialize() {
+        sources.push(hpet);
+        events.push(hpet);
+    }
+    sources.push(tsc::initialize());
+    events.push(apic::initialize());
+    TIMER.select_source(&sources);
+    TIMER.select_event(&events);
 }
@@ -0,0 +1,84 @@
+// NeurOS - Hobbyist operating system written in Rust.
+// Copyright (C) 2024 Theomund
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program. If not, see <https://www.gnu.org/licenses/>.
+
+use crate::cpu::{self, Cpu};
+use crate::ipi;
+use alloc::vec::Vec;
+use core::ptr;
+use x86_64::instructions::tlb;
+use x86_64::structures::paging::{Page, PhysFrame};
+use x86_64::VirtAddr;
+
+const BATCH: usize = 32;
+
+pub struct Shootdown {
+    space: PhysFrame,
+    pages: [VirtAddr; BATCH],
+    count: usize,
+    full: bool,
+}
+
+impl Shootdown {
+    pub fn new(space: PhysFrame) -> Shootdown {
+        Shootdown {
+            space,
+            pages: [VirtAddr::zero(); BATCH],
+            count: 0,
+            full: false,
+        }
+    }
+
+    pub fn add(&mut self, page: Page) {
+        if self.count == BATCH {
+            self.full = true;
+        } else {
+            self.pages[self.count] = page.start_address();
+            self.count += 1;
+        }
+    }
+
+    fn invalidate(&self) {
+        if self.full {
+            tlb::flush_all();
+        } else {
+            for address in &self.pages[..self.count] {
+                tlb::flush(*address);
+            }
+        }
+    }
+
+    fn is_running(&self, cpu: &Cpu) -> bool {
+        self.space.start_address().as_u64() == cpu.get_address_space()
+    }
+
+    pub fn flush(self) {
+        if self.count == 0 {
+            return;
+        }
+        let cpus = cpu::get_cpus();
+        if cpus.is_empty() {
+            self.invalidate();
+            return;
+        }
+        let targets: Vec<&Cpu> = cpus.into_iter().filter(|x| self.is_running(x)).collect();
+        ipi::call(&targets, invalidate, ptr::from_ref(&self) as u64);
+    }
+}
+
+fn invalidate(argument: u64) {
+    let shootdown = unsafe { &*(argument as *const Shootdown) };
+    shootdown.invalidate();
+}
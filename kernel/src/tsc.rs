@@ -0,0 +1,87 @@
+// NeurOS - Hobbyist operating system written in Rust.
+// Copyright (C) 2024 Theomund
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program. If not, see <https://www.gnu.org/licenses/>.
+
+use crate::logger::{Level, LOGGER};
+use crate::timer::{self, ClockSource};
+use crate::{debug, warn};
+use alloc::format;
+use core::arch::x86_64::{__cpuid, _rdtsc};
+use spin::Once;
+
+const CALIBRATION_NANOSECONDS: u64 = 10_000_000;
+const NANOSECONDS_PER_SECOND: u64 = 1_000_000_000;
+
+pub static TSC: Once<Tsc> = Once::new();
+
+pub struct Tsc {
+    base: u64,
+    frequency: u64,
+    invariant: bool,
+}
+
+impl Tsc {
+    pub fn new() -> Tsc {
+        let invariant = unsafe {
+            __cpuid(0x8000_0000).eax >= 0x8000_0007 && __cpuid(0x8000_0007).edx & (1 << 8) != 0
+        };
+        let start = Tsc::read();
+        timer::delay(CALIBRATION_NANOSECONDS);
+        let end = Tsc::read();
+        let frequency = (end - start) * NANOSECONDS_PER_SECOND / CALIBRATION_NANOSECONDS;
+        Tsc {
+            base: start,
+            frequency,
+            invariant,
+        }
+    }
+
+    pub fn read() -> u64 {
+        unsafe { _rdtsc() }
+    }
+}
+
+impl ClockSource for Tsc {
+    fn get_name(&self) -> &'static str {
+        "TSC"
+    }
+
+    fn get_rating(&self) -> u32 {
+        if self.invariant {
+            300
+        } else {
+            50
+        }
+    }
+
+    fn get_nanoseconds(&self) -> u64 {
+        let cycles = u128::from(Tsc::read() - self.base);
+        let nanoseconds = cycles * u128::from(NANOSECONDS_PER_SECOND) / u128::from(self.frequency);
+        u64::try_from(nanoseconds).unwrap_or(u64::MAX)
+    }
+}
+
+pub fn initialize() -> &'static Tsc {
+    let tsc = TSC.call_once(Tsc::new);
+    if tsc.invariant {
+        debug!("Calibrated the invariant TSC to {} Hz.", tsc.frequency);
+    } else {
+        warn!(
+            "Calibrated the TSC to {} Hz, but it isn't invariant.",
+            tsc.frequency
+        );
+    }
+    tsc
+}
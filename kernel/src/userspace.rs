@@ -14,11 +14,131 @@
 // You should have received a copy of the GNU General Public License
 // along with this program. If not, see <https://www.gnu.org/licenses/>.
 
-use crate::gdt;
+use crate::elf::Elf;
+use crate::initrd::INITRD;
+use crate::logger::{Level, LOGGER};
+use crate::process::{self, Process, INIT_PID, PROCESSES};
+use crate::rlimit::{Limits, Resource};
+use crate::syscall::Errno;
+use crate::{
+    error, gdt, info, interrupts, keyboard, memory, scheduler, serial, shell, thread, warn,
+};
+use alloc::format;
 use x86_64::registers::control::{Efer, EferFlags};
-use x86_64::registers::model_specific::Star;
+use x86_64::registers::model_specific::{LStar, SFMask, Star};
+use x86_64::registers::rflags::RFlags;
+use x86_64::structures::paging::{PageTableFlags, PhysFrame};
 
-pub fn initialize() {
+const INIT_PATH: &str = "initrd/bin/init";
+const PAGE_SIZE: u64 = 4096;
+const STACK_TOP: u64 = 0x_7FFF_FFFF_F000;
+const STACK_SIZE: u64 = 0x10000;
+// Zeroed argc, argv and envp terminators and an empty auxiliary vector.
+const ENTRY_FRAME: u64 = 6 * 8;
+
+fn load(path: &str, limits: &Limits) -> Result<(PhysFrame, u64), Errno> {
+    let data = INITRD.find(path).ok_or(Errno::NoEntry)?;
+    let elf = Elf::parse(data)
+        .filter(Elf::is_executable)
+        .ok_or(Errno::NoExec)?;
+    let size = elf
+        .get_segments()
+        .map(|x| x.get_memory_size().next_multiple_of(PAGE_SIZE))
+        .sum::<u64>()
+        + STACK_SIZE;
+    if !limits.get(Resource::Stack).allows(STACK_SIZE)
+        || !limits.get(Resource::AddressSpace).allows(size)
+    {
+        return Err(Errno::NoMemory);
+    }
+    let space = memory::create_address_space()?;
+    if let Err(errno) = populate(space, &elf, data) {
+        memory::destroy_address_space(space);
+        return Err(errno);
+    }
+    Ok((space, elf.get_entrypoint()))
+}
+
+fn populate(space: PhysFrame, elf: &Elf, data: &[u8]) -> Result<(), Errno> {
+    for segment in elf.get_segments() {
+        if segment.get_file_size() > segment.get_memory_size() {
+            return Err(Errno::NoExec);
+        }
+        let mut flags = PageTableFlags::empty();
+        if segment.is_writable() {
+            flags |= PageTableFlags::WRITABLE;
+        }
+        if !segment.is_executable() {
+            flags |= PageTableFlags::NO_EXECUTE;
+        }
+        let address = segment.get_virtual_address();
+        let alignment = segment.get_alignment();
+        if alignment > 1
+            && (!alignment.is_power_of_two()
+                || address.wrapping_sub(segment.get_offset()) & (alignment - 1) != 0)
+        {
+            return Err(Errno::NoExec);
+        }
+        memory::map_user(space, address, segment.get_memory_size(), flags)?;
+        let start = usize::try_from(segment.get_offset()).map_err(|_| Errno::NoExec)?;
+        let length = usize::try_from(segment.get_file_size()).map_err(|_| Errno::NoExec)?;
+        let bytes = start
+            .checked_add(length)
+            .and_then(|end| data.get(start..end))
+            .ok_or(Errno::NoExec)?;
+        memory::copy_to_user(space, address, bytes)?;
+    }
+    let flags = PageTableFlags::WRITABLE | PageTableFlags::NO_EXECUTE;
+    memory::map_user(space, STACK_TOP - STACK_SIZE, STACK_SIZE, flags)?;
+    Ok(())
+}
+
+pub fn spawn(path: &str, pid: Option<u64>) -> Result<u64, Errno> {
+    let parent = process::get_current();
+    let limits = PROCESSES
+        .lock()
+        .get(parent)
+        .map(|x| x.get_limits().clone())
+        .unwrap_or_default();
+    let (space, entry) = load(path, &limits)?;
+    let mut table = PROCESSES.lock();
+    let pid = pid
+        .or_else(|| table.allocate())
+        .filter(|_| limits.get(Resource::Processes).allows(table.count() + 1));
+    let Some(pid) = pid else {
+        drop(table);
+        memory::destroy_address_space(space);
+        return Err(Errno::Again);
+    };
+    let name = path.rsplit('/').next().unwrap_or(path);
+    let mut process = Process::user(pid, name, space, entry, STACK_TOP - ENTRY_FRAME);
+    process.set_parent(parent);
+    process.set_limits(limits);
+    table.insert(process);
+    drop(table);
+    scheduler::place(pid);
+    Ok(pid)
+}
+
+fn monitor() {
+    match spawn(INIT_PATH, Some(INIT_PID)) {
+        Ok(pid) => {
+            info!("Started {INIT_PATH} as process #{pid}.");
+            serial::set_foreground(pid);
+            keyboard::set_foreground(pid);
+            if let Ok(Some((_, status))) = process::wait(Some(pid), true) {
+                warn!("The init process exited with status {status}.");
+            }
+        }
+        Err(errno) => {
+            error!("Failed to start {INIT_PATH} ({errno:?}).");
+        }
+    }
+    warn!("Falling back to the kernel shells.");
+    shell::initialize();
+}
+
+pub fn initialize_cpu() {
     Star::write(
         gdt::get_user_code(),
         gdt::get_user_data(),
@@ -35,4 +155,17 @@ pub fn initialize() {
                 | EferFlags::NO_EXECUTE_ENABLE,
         );
     }
+
+    LStar::write(interrupts::get_system_call_entry());
+    SFMask::write(
+        RFlags::INTERRUPT_FLAG
+            | RFlags::DIRECTION_FLAG
+            | RFlags::TRAP_FLAG
+            | RFlags::ALIGNMENT_CHECK,
+    );
+}
+
+pub fn initialize() {
+    initialize_cpu();
+    thread::spawn("init-monitor", monitor);
 }
@@ -14,25 +14,25 @@
 // You should have received a copy of the GNU General Public License
 // along with this program. If not, see <https://www.gnu.org/licenses/>.
 
-use crate::ansi::{BLUE, BOLD, DEFAULT, GREEN, NORMAL, ORANGE, PURPLE, RED, YELLOW};
+use crate::ansi::{BLUE, BOLD, CLEAR, DEFAULT, GREEN, HOME, NORMAL, ORANGE, PURPLE, RED, YELLOW};
 use crate::font::Font;
-use alloc::format;
+use crate::sync::IrqMutex;
 use alloc::string::{String, ToString};
 use core::fmt::{Arguments, Result, Write};
 use core::ptr;
 use core::sync::atomic::{AtomicPtr, Ordering};
 use limine::request::FramebufferRequest;
-use spin::{Lazy, Mutex};
+use spin::Lazy;
 use x86_64::instructions::interrupts::without_interrupts;
 
 #[used]
 #[link_section = ".requests"]
 static FRAMEBUFFER_REQUEST: FramebufferRequest = FramebufferRequest::new();
 
-pub static VGA: Lazy<Mutex<Vga>> = Lazy::new(|| {
+pub static VGA: Lazy<IrqMutex<Vga>> = Lazy::new(|| {
     let font = Font::new("initrd/usr/share/fonts/ter-i16n.psf");
     let vga = Vga::new(font);
-    Mutex::new(vga)
+    IrqMutex::new("VGA", vga)
 });
 
 #[derive(Clone, Copy)]
@@ -69,30 +69,32 @@ impl Write for Vga {
         let mut y = self.cursor.y;
         let mut fg = self.cursor.fg;
         let bg = self.cursor.bg;
+        let width = self.font.get_width();
 
         let chars = &mut s.chars();
-        let mut position = 0;
 
         while let Some(character) = chars.next() {
             match character {
                 '\n' => {
-                    x = self.font.get_width();
-                    y += self.font.get_height();
-                    position = 0;
-                    self.set_cursor(x, y, fg, bg);
+                    (x, y) = self.next_line(y);
                 }
                 '\r' => {
-                    x = self.font.get_width();
-                    position = 0;
-                    self.set_cursor(x, y, fg, bg);
+                    x = width;
                 }
                 '\t' => {
-                    x += self.font.get_width() * 8;
-                    self.set_cursor(x, y, fg, bg);
+                    x += width * 8;
+                }
+                '\x08' => {
+                    x = x.saturating_sub(width).max(width);
                 }
                 '\x1b' => {
-                    let mut sequence: String = chars.take_while(|x| *x != 'm').collect();
-                    sequence = format!("\x1b{sequence}m");
+                    let mut sequence = String::from(character);
+                    for next in chars.by_ref() {
+                        sequence.push(next);
+                        if next.is_ascii_alphabetic() {
+                            break;
+                        }
+                    }
                     match sequence.as_str() {
                         BOLD => {
                             self.font = Font::new("initrd/usr/share/fonts/ter-i16b.psf");
@@ -100,12 +102,20 @@ impl Write for Vga {
                         BLUE => {
                             fg = Color::Blue as u32;
                         }
+                        CLEAR => {
+                            self.clear();
+                            (x, y) = (self.cursor.x, self.cursor.y);
+                        }
                         DEFAULT => {
                             fg = Color::White as u32;
                         }
                         GREEN => {
                             fg = Color::Green as u32;
                         }
+                        HOME => {
+                            x = width;
+                            y = self.get_font_height() + width;
+                        }
                         NORMAL => {
                             self.font = Font::new("initrd/usr/share/fonts/ter-i16n.psf");
                         }
@@ -125,13 +135,17 @@ impl Write for Vga {
                     }
                 }
                 _ => {
-                    self.set_cursor(x + self.font.get_width() * position, y, fg, bg);
+                    if x + width > self.get_width() {
+                        (x, y) = self.next_line(y);
+                    }
+                    self.set_cursor(x, y, fg, bg);
                     self.write_char(character)?;
-                    position += 1;
+                    x += width;
                 }
             }
         }
 
+        self.set_cursor(x, y, fg, bg);
         Ok(())
     }
 
@@ -222,6 +236,15 @@ impl Vga {
         self.font.get_height()
     }
 
+    fn next_line(&mut self, y: usize) -> (usize, usize) {
+        let height = self.font.get_height();
+        if y + height * 2 > self.get_height() {
+            self.clear();
+            return (self.cursor.x, self.cursor.y);
+        }
+        (self.font.get_width(), y + height)
+    }
+
     pub fn set_cursor(&mut self, x: usize, y: usize, fg: u32, bg: u32) {
         self.cursor = Cursor { x, y, fg, bg }
     }
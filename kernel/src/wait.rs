@@ -0,0 +1,66 @@
+// NeurOS - Hobbyist operating system written in Rust.
+// Copyright (C) 2024 Theomund
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program. If not, see <https://www.gnu.org/licenses/>.
+
+use crate::process::{self, State};
+use crate::scheduler;
+use crate::sync::IrqMutex;
+use alloc::collections::VecDeque;
+
+pub struct Queue {
+    waiters: IrqMutex<VecDeque<u64>>,
+}
+
+impl Queue {
+    pub const fn new(name: &'static str) -> Queue {
+        Queue {
+            waiters: IrqMutex::new(name, VecDeque::new()),
+        }
+    }
+
+    fn register(&self, pid: u64) {
+        let mut waiters = self.waiters.lock();
+        if !waiters.contains(&pid) {
+            waiters.push_back(pid);
+        }
+    }
+
+    fn unregister(&self, pid: u64) {
+        self.waiters.lock().retain(|x| *x != pid);
+    }
+
+    pub fn wait_until<F: FnMut() -> bool>(&self, mut condition: F) {
+        let pid = process::get_current();
+        scheduler::block_until(State::Blocked, || {
+            self.register(pid);
+            condition()
+        });
+        self.unregister(pid);
+    }
+
+    pub fn wake_one(&self) {
+        let pid = self.waiters.lock().pop_front();
+        if let Some(pid) = pid {
+            scheduler::wake(pid);
+        }
+    }
+
+    pub fn wake_all(&self) {
+        let waiters: VecDeque<u64> = core::mem::take(&mut *self.waiters.lock());
+        for pid in waiters {
+            scheduler::wake(pid);
+        }
+    }
+}
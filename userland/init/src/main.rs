@@ -17,12 +17,54 @@
 #![no_std]
 #![no_main]
 
+use core::arch::{asm, global_asm};
 use core::panic::PanicInfo;
 
+const WRITE: u64 = 1;
+const EXIT: u64 = 60;
+const STDOUT: u64 = 1;
+
+global_asm!(
+    ".global _start",
+    "_start:",
+    "    xor rbp, rbp",
+    "    call main",
+    "    ud2",
+);
+
+fn syscall(number: u64, first: u64, second: u64, third: u64) -> u64 {
+    let result;
+    unsafe {
+        asm!(
+            "syscall",
+            inlateout("rax") number => result,
+            in("rdi") first,
+            in("rsi") second,
+            in("rdx") third,
+            out("rcx") _,
+            out("r11") _,
+            options(nostack),
+        );
+    }
+    result
+}
+
+fn write(message: &str) {
+    syscall(WRITE, STDOUT, message.as_ptr() as u64, message.len() as u64);
+}
+
+fn exit(status: u64) -> ! {
+    syscall(EXIT, status, 0, 0);
+    unreachable!();
+}
+
 #[no_mangle]
-fn main() {}
+extern "C" fn main() -> ! {
+    write("Hello from init!\n");
+    exit(0);
+}
 
 #[panic_handler]
 fn panic(_info: &PanicInfo) -> ! {
-    loop {}
+    exit(101);
 }